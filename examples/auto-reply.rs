@@ -1,7 +1,6 @@
 use std::env;
 
 const BOT_ACCESS_TOKEN: &str = "BOT_ACCESS_TOKEN";
-const BOT_EMAIL: &str = "BOT_EMAIL";
 
 ///
 /// # Autoreply
@@ -11,7 +10,7 @@ const BOT_EMAIL: &str = "BOT_EMAIL";
 ///
 /// # Usage
 ///
-/// BOT_ACCESS_TOKEN="<token>" BOT_EMAIL="botname@webex.bot" cargo run --example auto-reply
+/// BOT_ACCESS_TOKEN="<token>" cargo run --example auto-reply
 ///
 /// You can obtain a bot token by logging into the [Cisco Webex developer site](https://developer.webex.com/), then
 ///
@@ -27,10 +26,15 @@ const BOT_EMAIL: &str = "BOT_EMAIL";
 async fn main() {
     let token = env::var(BOT_ACCESS_TOKEN)
         .unwrap_or_else(|_| panic!("{} not specified in environment", BOT_ACCESS_TOKEN));
-    let bot_email = env::var(BOT_EMAIL)
-        .unwrap_or_else(|_| panic!("{} not specified in environment", BOT_EMAIL));
 
-    let webex = webex::Webex::new(token.as_str()).await;
+    let webex = webex::Webex::try_new(token.as_str())
+        .await
+        .expect("valid bot token");
+    let bot_email = webex
+        .identity()
+        .and_then(|me| me.emails.first())
+        .cloned()
+        .expect("bot identity with an email address");
     let mut event_stream = webex.event_stream().await.expect("event stream");
 
     while let Ok(event) = event_stream.next().await {
@@ -46,7 +50,7 @@ async fn main() {
                     // In practice, this shouldn't happen since bots can't see messages
                     // that don't specifically mention them (i.e., appears in the special
                     // "mentions" field).
-                    Some(sender) if sender != bot_email.as_str() => {
+                    Some(sender) if *sender != bot_email => {
                         let mut reply = webex::types::MessageOut::from(&msg);
                         reply.text = Some(format!("{}, you said: {}", sender, msg.text.unwrap()));
                         webex.send_message(&reply).await.unwrap();
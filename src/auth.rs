@@ -113,16 +113,13 @@ impl DeviceAuthenticator {
                 .await
             {
                 Ok(token) => return Ok(token.access_token),
-                Err(e) => match e {
-                    crate::error::Error::StatusText(http_status, _) => {
-                        if http_status != StatusCode::PRECONDITION_REQUIRED {
-                            return Err(crate::Error::Authentication);
-                        }
-                    }
-                    _ => {
-                        return Err(crate::Error::Authentication);
-                    }
-                },
+                // Still waiting for the user
+                Err(e) if e.status() == Some(StatusCode::PRECONDITION_REQUIRED) => {}
+                Err(e) => {
+                    return Err(crate::Error::Authentication(
+                        e.api_error().cloned().map(Box::new),
+                    ));
+                }
             }
         }
     }
@@ -0,0 +1,636 @@
+#![deny(missing_docs)]
+//! Framework for bots that react to commands, patterns, card submissions and membership changes
+//!
+//! A [`Router`] maps incoming events to async handlers; a [`Bot`] feeds it from a
+//! [`ReconnectingEventStream`](crate::ReconnectingEventStream), fetching messages, skipping the
+//! bot's own messages and stripping the bot mention before dispatching.
+//!
+//! ```no_run
+//! use webex::bot::{Bot, Context, Router};
+//!
+//! # async fn example() -> Result<(), webex::error::Error> {
+//! let webex = webex::Webex::new("token").await;
+//! let router = Router::new()
+//!     .command("echo", "`echo <text>` - repeat the text", |ctx: Context| async move {
+//!         ctx.reply(&ctx.args.join(" ")).await?;
+//!         Ok(())
+//!     })
+//!     .command("add", "`add <a> <b>` - add two numbers", |ctx: Context| async move {
+//!         let sum = ctx.arg::<i64>(0)? + ctx.arg::<i64>(1)?;
+//!         ctx.reply(&sum.to_string()).await?;
+//!         Ok(())
+//!     });
+//! Bot::new(webex, router).max_concurrency(4).run().await
+//! # }
+//! ```
+
+use crate::{
+    error::Error, AttachmentAction, Message, MessageOut, Person, StreamEvent, Webex, WebexEvent,
+};
+use futures::future::BoxFuture;
+use log::{debug, warn};
+use std::{fmt::Write, future::Future, str::FromStr, sync::Arc};
+use tokio::sync::{OnceCell, Semaphore};
+
+pub use regex::Regex;
+
+type Handler = Arc<dyn Fn(Context) -> BoxFuture<'static, Result<(), Error>> + Send + Sync>;
+
+fn boxed<F, Fut>(handler: F) -> Handler
+where
+    F: Fn(Context) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), Error>> + Send + 'static,
+{
+    Arc::new(move |ctx| Box::pin(handler(ctx)))
+}
+
+/// Everything a handler gets to know about the event it is called for.
+#[derive(Clone)]
+pub struct Context {
+    /// Client to make further requests with.
+    pub webex: Webex,
+    /// The event that triggered the handler.
+    pub event: WebexEvent,
+    /// Room the event happened in, where [`reply()`](Self::reply) posts to.
+    pub room_id: Option<String>,
+    /// The received message, for command, pattern and fallback handlers.
+    pub message: Option<Message>,
+    /// The submitted card inputs, for card handlers.
+    pub action: Option<AttachmentAction>,
+    /// Message text with the bot mention removed. For command handlers, the text after the
+    /// command word.
+    pub text: String,
+    /// For command handlers, the arguments following the command word. Arguments are separated
+    /// by whitespace, and can be grouped with double quotes (`say "hello world"`).
+    pub args: Vec<String>,
+    /// For pattern handlers, the capture groups of the match (index 0 is the whole match).
+    pub captures: Vec<Option<String>>,
+}
+
+impl Context {
+    const fn new(webex: Webex, event: WebexEvent) -> Self {
+        Self {
+            webex,
+            event,
+            room_id: None,
+            message: None,
+            action: None,
+            text: String::new(),
+            args: Vec::new(),
+            captures: Vec::new(),
+        }
+    }
+
+    /// Parse argument `index` (starting at 0) of a command.
+    ///
+    /// # Errors
+    /// If the argument is missing or doesn't parse, with a message suitable for the user.
+    pub fn arg<T: FromStr>(&self, index: usize) -> Result<T, Error>
+    where
+        T::Err: std::fmt::Display,
+    {
+        let arg = self
+            .args
+            .get(index)
+            .ok_or_else(|| format!("missing argument {}", index + 1))?;
+        arg.parse()
+            .map_err(|e| format!("argument {} (`{arg}`) is invalid: {e}", index + 1).into())
+    }
+
+    /// Post a markdown message to the room of the event, in the same thread as the received
+    /// message if there is one.
+    pub async fn reply(&self, markdown: &str) -> Result<Message, Error> {
+        let mut reply = match &self.message {
+            Some(message) if message.room_id.is_some() => message.reply(),
+            _ => MessageOut {
+                room_id: Some(
+                    self.room_id
+                        .clone()
+                        .ok_or("event has no room to reply to")?,
+                ),
+                ..MessageOut::default()
+            },
+        };
+        reply.markdown = Some(markdown.to_string());
+        self.webex.send_message(&reply).await
+    }
+}
+
+/// Maps events to handlers.
+///
+/// Messages are dispatched to the first matching command (by first word, case-insensitive, with
+/// an optional leading `/`), else to the first matching pattern, else to the fallback handler. A
+/// `help` command listing all commands is answered automatically unless one is registered.
+#[must_use]
+#[derive(Clone, Default)]
+pub struct Router {
+    commands: Vec<(String, String, Handler)>,
+    patterns: Vec<(Regex, Handler)>,
+    card: Option<Handler>,
+    membership: Option<Handler>,
+    fallback: Option<Handler>,
+}
+
+impl Router {
+    /// Creates a router without any handlers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle messages starting with the word `name`. `help` is the line listed for this command
+    /// in the help text.
+    pub fn command<F, Fut>(mut self, name: &str, help: &str, handler: F) -> Self
+    where
+        F: Fn(Context) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        self.commands
+            .push((name.to_lowercase(), help.to_string(), boxed(handler)));
+        self
+    }
+
+    /// Handle messages matching the regular expression `pattern` that aren't commands. See the
+    /// [`regex` syntax](https://docs.rs/regex/latest/regex/#syntax).
+    pub fn pattern<F, Fut>(mut self, pattern: Regex, handler: F) -> Self
+    where
+        F: Fn(Context) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        self.patterns.push((pattern, boxed(handler)));
+        self
+    }
+
+    /// Handle adaptive card submissions.
+    pub fn card<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(Context) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        self.card = Some(boxed(handler));
+        self
+    }
+
+    /// Handle [`WebexEvent::MembershipJoined`] and [`WebexEvent::MembershipLeft`] events.
+    pub fn membership<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(Context) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        self.membership = Some(boxed(handler));
+        self
+    }
+
+    /// Handle messages that match no command or pattern.
+    pub fn fallback<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(Context) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        self.fallback = Some(boxed(handler));
+        self
+    }
+
+    /// Markdown list of the registered commands, as sent for `help`.
+    #[must_use]
+    pub fn help_text(&self) -> String {
+        let mut help = "Available commands:\n".to_string();
+        for (name, text, _) in &self.commands {
+            let _ = writeln!(help, "- **{name}**: {text}");
+        }
+        if !self.has_command("help") {
+            help.push_str("- **help**: show this list\n");
+        }
+        help
+    }
+
+    fn has_command(&self, name: &str) -> bool {
+        self.commands.iter().any(|(command, _, _)| command == name)
+    }
+}
+
+/// Runs a [`Router`] on the events of a [`Webex`] client.
+#[must_use]
+pub struct Bot {
+    webex: Webex,
+    router: Router,
+    max_concurrency: usize,
+    report_errors: bool,
+    me: OnceCell<Person>,
+}
+
+impl Bot {
+    /// Creates a bot that handles up to 8 events at once and reports handler errors.
+    pub fn new(webex: Webex, router: Router) -> Self {
+        let webex_identity = webex.identity().cloned();
+        Self {
+            webex,
+            router,
+            max_concurrency: 8,
+            report_errors: true,
+            me: OnceCell::new_with(webex_identity),
+        }
+    }
+
+    /// Maximum number of events handled at the same time. Further events wait until a handler
+    /// finishes. `0` is treated as `1`.
+    pub const fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = if max_concurrency == 0 {
+            1
+        } else {
+            max_concurrency
+        };
+        self
+    }
+
+    /// Whether to post a message to the room when a handler returns an error. Errors are logged
+    /// either way.
+    pub const fn report_errors(mut self, report_errors: bool) -> Self {
+        self.report_errors = report_errors;
+        self
+    }
+
+    /// Listen for events and handle them until the event stream fails for good. Events that
+    /// can't be read (e.g. because they fail to deserialize) are logged and skipped.
+    ///
+    /// # Errors
+    /// If the event stream cannot be opened, or reconnecting to it fails.
+    pub async fn run(self) -> Result<(), Error> {
+        let mut stream = self.webex.reconnecting_event_stream().await?;
+        let semaphore = Arc::new(Semaphore::new(self.max_concurrency));
+        let bot = Arc::new(self);
+        loop {
+            let event = match stream.next().await {
+                Ok(StreamEvent::Event(event)) => WebexEvent::from(&*event),
+                Ok(StreamEvent::Reconnected { .. }) => continue,
+                Err(e) if stream.is_connected() => {
+                    warn!("Skipping event: {e}");
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let permit = semaphore
+                .clone()
+                .acquire_owned()
+                .await
+                .map_err(|e| e.to_string())?;
+            let bot = bot.clone();
+            tokio::spawn(async move {
+                if let Err(e) = bot.handle(event).await {
+                    warn!("Failed to handle event: {e}");
+                }
+                drop(permit);
+            });
+        }
+    }
+
+    /// Dispatch a single event to its handler and wait for it to finish. [`run()`](Self::run)
+    /// does this for each received event; call it directly to feed events from elsewhere.
+    ///
+    /// # Errors
+    /// If the message or card submission of the event cannot be fetched. Errors returned by
+    /// handlers are reported, not returned, and so are failures to report them.
+    pub async fn handle(&self, mut event: WebexEvent) -> Result<(), Error> {
+        self.webex.enrich_event(&mut event).await?;
+        let mut ctx = Context::new(self.webex.clone(), event.clone());
+        let handler = match event {
+            WebexEvent::MessageCreated {
+                message: Some(message),
+                ..
+            } => {
+                let me = self.me().await?;
+                if message.person_id.as_deref() == Some(me.id.as_str()) {
+                    return Ok(());
+                }
+                ctx.room_id.clone_from(&message.room_id);
+                ctx.text = strip_mention(&message, &me.display_name);
+                ctx.message = Some(*message);
+                self.route_message(&mut ctx)
+            }
+            WebexEvent::CardSubmitted {
+                action: Some(action),
+                ..
+            } => {
+                ctx.room_id.clone_from(&action.room_id);
+                ctx.action = Some(*action);
+                self.router.card.clone()
+            }
+            WebexEvent::MembershipJoined { room_id, .. }
+            | WebexEvent::MembershipLeft { room_id, .. } => {
+                ctx.room_id = Some(room_id.id().to_string());
+                self.router.membership.clone()
+            }
+            _ => None,
+        };
+        let Some(handler) = handler else {
+            return Ok(());
+        };
+        let room_id = ctx.room_id.clone();
+        if let Err(e) = handler(ctx).await {
+            warn!("Handler failed: {e}");
+            if let (true, Some(room_id)) = (self.report_errors, room_id) {
+                let report = MessageOut {
+                    room_id: Some(room_id),
+                    text: Some(format!("Sorry, that didn't work: {e}")),
+                    ..MessageOut::default()
+                };
+                if let Err(e) = self.webex.send_message(&report).await {
+                    warn!("Failed to report handler error: {e}");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Pick the handler for a message, filling in the command arguments or pattern captures.
+    fn route_message(&self, ctx: &mut Context) -> Option<Handler> {
+        let (word, rest) = ctx
+            .text
+            .split_once(char::is_whitespace)
+            .unwrap_or((&ctx.text, ""));
+        let word = word.trim_start_matches('/').to_lowercase();
+        let rest = rest.trim().to_string();
+        if let Some((_, _, handler)) = self
+            .router
+            .commands
+            .iter()
+            .find(|(name, _, _)| *name == word)
+        {
+            debug!("Dispatching command {word}");
+            ctx.args = split_args(&rest);
+            ctx.text = rest;
+            return Some(handler.clone());
+        }
+        if word == "help" {
+            let help = self.router.help_text();
+            return Some(boxed(move |ctx: Context| {
+                let help = help.clone();
+                async move { ctx.reply(&help).await.map(|_| ()) }
+            }));
+        }
+        for (pattern, handler) in &self.router.patterns {
+            if let Some(captures) = pattern.captures(&ctx.text) {
+                ctx.captures = captures
+                    .iter()
+                    .map(|group| group.map(|group| group.as_str().to_string()))
+                    .collect();
+                return Some(handler.clone());
+            }
+        }
+        self.router.fallback.clone()
+    }
+
+    async fn me(&self) -> Result<&Person, Error> {
+        self.me.get_or_try_init(|| self.webex.me()).await
+    }
+}
+
+/// Message text without the leading mention of the bot. Group messages start with the mention,
+/// whose text is taken from the `<spark-mention>` in the HTML, falling back to the bot's name.
+fn strip_mention(message: &Message, display_name: &str) -> String {
+    let text = message.text.as_deref().unwrap_or_default().trim_start();
+    let mention = message
+        .html
+        .as_deref()
+        .and_then(|html| html.split_once("<spark-mention"))
+        .and_then(|(_, mention)| mention.split_once('>'))
+        .and_then(|(_, mention)| mention.split_once("</spark-mention>"))
+        .map(|(name, _)| name);
+    mention
+        .into_iter()
+        .chain([display_name])
+        .filter(|name| !name.is_empty())
+        .find_map(|name| text.strip_prefix(name))
+        .unwrap_or(text)
+        .trim()
+        .to_string()
+}
+
+/// Split command arguments on whitespace, keeping double-quoted parts together.
+fn split_args(text: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_arg = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::MockWebex, tests::mock_server, GlobalId, GlobalIdType, RoomType};
+    use std::time::Duration;
+
+    #[test]
+    fn argument_splitting() {
+        assert_eq!(
+            split_args(r#"one  "two three" "" four"#),
+            ["one", "two three", "", "four"]
+        );
+        assert!(split_args("  ").is_empty());
+    }
+
+    #[test]
+    fn mention_stripping() {
+        let message = Message {
+            text: Some("Helper echo hi".to_string()),
+            html: Some(
+                r#"<p><spark-mention data-object-type="person" data-object-id="x">Helper</spark-mention> echo hi</p>"#
+                    .to_string(),
+            ),
+            ..Message::default()
+        };
+        assert_eq!(strip_mention(&message, "Helper Bot"), "echo hi");
+        let direct = Message {
+            text: Some("Helper Bot echo hi".to_string()),
+            ..Message::default()
+        };
+        assert_eq!(strip_mention(&direct, "Helper Bot"), "echo hi");
+    }
+
+    fn message_event() -> WebexEvent {
+        WebexEvent::MessageCreated {
+            id: GlobalId::new_with_cluster_unchecked(GlobalIdType::Message, "m".into(), None),
+            room_id: None,
+            person_id: None,
+            message: None,
+        }
+    }
+
+    async fn bot(
+        router: Router,
+        message: &'static str,
+    ) -> (Bot, Arc<std::sync::Mutex<Vec<String>>>) {
+        let (prefix, requests) = mock_server(vec![
+            ("200 OK", "", message.to_string()),
+            ("200 OK", "", r#"{"id":"m"}"#.to_string()),
+            ("200 OK", "", r#"{"id":"reply"}"#.to_string()),
+        ])
+        .await;
+        let webex = Webex::builder("token")
+            .rest_url(prefix)
+            .wdm_url("http://localhost")
+            .build()
+            .await
+            .unwrap();
+        (Bot::new(webex, router), requests)
+    }
+
+    #[tokio::test]
+    async fn dispatches_commands() {
+        let (args_tx, mut args_rx) = tokio::sync::mpsc::unbounded_channel();
+        let router = Router::new().command("Echo", "", move |ctx: Context| {
+            let args_tx = args_tx.clone();
+            async move {
+                args_tx.send(ctx.args.clone()).unwrap();
+                ctx.reply(&ctx.text).await?;
+                Ok(())
+            }
+        });
+        let (bot, requests) = bot(
+            router,
+            r#"{"id":"m","roomId":"r","personId":"someone","text":"/echo a \"b c\""}"#,
+        )
+        .await;
+        bot.handle(message_event()).await.unwrap();
+        assert_eq!(args_rx.recv().await.unwrap(), ["a", "b c"]);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                "GET /messages/m HTTP/1.1",
+                "GET /people/me HTTP/1.1",
+                "POST /messages HTTP/1.1"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn dispatches_patterns() {
+        let (captures_tx, mut captures_rx) = tokio::sync::mpsc::unbounded_channel();
+        let router = Router::new().pattern(
+            Regex::new(r"ticket #(\d+)").unwrap(),
+            move |ctx: Context| {
+                captures_tx.send(ctx.captures).unwrap();
+                async { Ok(()) }
+            },
+        );
+        let (bot, _) = bot(
+            router,
+            r#"{"id":"m","roomId":"r","personId":"someone","text":"look at ticket #42"}"#,
+        )
+        .await;
+        bot.handle(message_event()).await.unwrap();
+        assert_eq!(
+            captures_rx.recv().await.unwrap(),
+            [Some("ticket #42".to_string()), Some("42".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_handler_errors() {
+        let mock = MockWebex::start().await;
+        let alice = mock.add_person("Alice", "alice@example.com");
+        let room = mock.add_room("Test room", RoomType::Group);
+        let router = Router::new().command("add", "", |ctx: Context| async move {
+            ctx.arg::<i64>(0)?;
+            panic!("argument should not parse");
+        });
+        let bot = run(&mock, Bot::new(mock.client().await.unwrap(), router)).await;
+
+        mock.post_message(&alice, &room, "add x");
+        let report = tokio::time::timeout(Duration::from_secs(10), mock.next_sent_message())
+            .await
+            .unwrap();
+        assert_eq!(report.room_id, Some(room.id));
+        assert_eq!(
+            report.text.as_deref(),
+            Some(
+                "Sorry, that didn't work: Unknown error: argument 1 (`x`) is invalid: \
+                 invalid digit found in string"
+            )
+        );
+        bot.abort();
+    }
+
+    #[tokio::test]
+    async fn ignores_own_messages() {
+        let (bot, requests) = bot(
+            Router::new().fallback(|_| async { panic!("should not be called") }),
+            r#"{"id":"m","roomId":"r","personId":"m","text":"hi"}"#,
+        )
+        .await;
+        bot.handle(message_event()).await.unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn skips_unreadable_events() {
+        let mock = MockWebex::start().await;
+        let alice = mock.add_person("Alice", "alice@example.com");
+        let room = mock.add_room("Test room", RoomType::Group);
+        let router = Router::new().command("echo", "", |ctx: Context| async move {
+            ctx.reply(&ctx.args.join(" ")).await?;
+            Ok(())
+        });
+        let bot = run(&mock, Bot::new(mock.client().await.unwrap(), router)).await;
+
+        mock.send_binary_frame(b"not an event".to_vec());
+        mock.post_message(&alice, &room, "echo hi");
+        let reply = tokio::time::timeout(Duration::from_secs(10), mock.next_sent_message())
+            .await
+            .unwrap();
+        assert_eq!(reply.markdown.as_deref(), Some("hi"));
+        assert!(!bot.is_finished());
+        assert_eq!(mock.event_stream_connections(), 1);
+        bot.abort();
+    }
+
+    #[tokio::test]
+    async fn zero_concurrency_still_handles_events() {
+        let mock = MockWebex::start().await;
+        let alice = mock.add_person("Alice", "alice@example.com");
+        let room = mock.add_room("Test room", RoomType::Group);
+        let router = Router::new().command("echo", "", |ctx: Context| async move {
+            ctx.reply(&ctx.args.join(" ")).await?;
+            Ok(())
+        });
+        let bot = Bot::new(mock.client().await.unwrap(), router).max_concurrency(0);
+        let bot = run(&mock, bot).await;
+
+        mock.post_message(&alice, &room, "echo hi");
+        let reply = tokio::time::timeout(Duration::from_secs(10), mock.next_sent_message())
+            .await
+            .unwrap();
+        assert_eq!(reply.markdown.as_deref(), Some("hi"));
+        bot.abort();
+    }
+
+    /// Runs `bot` in the background, returning once it is listening for events.
+    async fn run(mock: &MockWebex, bot: Bot) -> tokio::task::JoinHandle<Result<(), Error>> {
+        let bot = tokio::spawn(bot.run());
+        while mock.event_stream_connections() == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        bot
+    }
+}
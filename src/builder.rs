@@ -0,0 +1,356 @@
+#![deny(missing_docs)]
+//! Builder for [`Webex`] clients with non-default endpoints or HTTP settings
+
+use crate::{
+    catalog::CatalogCache, error::Error, people::PersonCache, retry::RetryPolicy, Cassette,
+    DeviceData, KeepaliveSettings, RestClient, Webex, CRATE_VERSION, DEFAULT_CATALOG_TTL,
+    DEFAULT_DEVICE_NAME, DEFAULT_PERSON_CACHE_SIZE, DEFAULT_PERSON_TTL,
+    DEFAULT_REGISTRATION_HOST_PREFIX, DEVICE_SYSTEM_NAME, REST_HOST_PREFIX, U2C_HOST_PREFIX,
+};
+use log::{debug, trace, warn};
+use std::time::Duration;
+
+/// Configures and creates a [`Webex`] client.
+///
+/// Every setting is optional; the defaults match [`Webex::new`]. Use this to point the client at
+/// a proxy, an alternate cluster or a local test server.
+///
+/// ```no_run
+/// # async fn example() -> Result<(), webex::error::Error> {
+/// let webex = webex::Webex::builder("token")
+///     .device_name("my-bot")
+///     .rest_url("https://webexapis.com/v1")
+///     .timeout(std::time::Duration::from_secs(30))
+///     .build()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[must_use]
+#[derive(Clone, Debug)]
+pub struct WebexBuilder {
+    token: String,
+    device_name: String,
+    rest_url: String,
+    u2c_url: String,
+    wdm_url: Option<String>,
+    mercury_url: Option<String>,
+    client: Option<reqwest::Client>,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    user_agent: Option<String>,
+    retry_policy: RetryPolicy,
+    keepalive: KeepaliveSettings,
+    validate_token: bool,
+    catalog_ttl: Duration,
+    person_ttl: Duration,
+    person_cache_size: usize,
+    org_id: Option<String>,
+    cassette: Option<Cassette>,
+}
+
+impl WebexBuilder {
+    /// Creates a builder for a client using the given token.
+    pub fn new(token: &str) -> Self {
+        Self {
+            token: token.to_string(),
+            device_name: DEFAULT_DEVICE_NAME.to_string(),
+            rest_url: REST_HOST_PREFIX.to_string(),
+            u2c_url: U2C_HOST_PREFIX.to_string(),
+            wdm_url: None,
+            mercury_url: None,
+            client: None,
+            timeout: None,
+            connect_timeout: None,
+            user_agent: None,
+            retry_policy: RetryPolicy::default(),
+            keepalive: KeepaliveSettings::default(),
+            validate_token: false,
+            catalog_ttl: DEFAULT_CATALOG_TTL,
+            person_ttl: DEFAULT_PERSON_TTL,
+            person_cache_size: DEFAULT_PERSON_CACHE_SIZE,
+            org_id: None,
+            cassette: None,
+        }
+    }
+
+    /// Name used to identify the device/client with the Webex API.
+    pub fn device_name(mut self, device_name: impl Into<String>) -> Self {
+        self.device_name = device_name.into();
+        self
+    }
+
+    /// Base URL for REST requests. Default: `https://api.ciscospark.com/v1`
+    pub fn rest_url(mut self, url: impl Into<String>) -> Self {
+        self.rest_url = trim_url(url.into());
+        self
+    }
+
+    /// Base URL of the U2C service catalog, used to discover the WDM URL.
+    /// Default: `https://u2c.wbx2.com/u2c/api/v1`
+    pub fn u2c_url(mut self, url: impl Into<String>) -> Self {
+        self.u2c_url = trim_url(url.into());
+        self
+    }
+
+    /// Base URL of the WDM (device registration) service. Setting this skips discovery through
+    /// the U2C catalog.
+    pub fn wdm_url(mut self, url: impl Into<String>) -> Self {
+        self.wdm_url = Some(trim_url(url.into()));
+        self
+    }
+
+    /// Websocket URL to connect event streams to, instead of the `webSocketUrl` of the registered
+    /// device.
+    pub fn mercury_url(mut self, url: impl Into<String>) -> Self {
+        self.mercury_url = Some(url.into());
+        self
+    }
+
+    /// HTTP client to use for REST requests. When set, [`timeout`](Self::timeout),
+    /// [`connect_timeout`](Self::connect_timeout) and [`user_agent`](Self::user_agent) are
+    /// ignored; configure them on the client instead.
+    pub fn http_client(mut self, client: reqwest::Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Total timeout for each REST request.
+    pub const fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Timeout for establishing the connection of each REST request.
+    pub const fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// `User-Agent` header sent with REST requests.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Retry policy for REST requests, see [`RetryPolicy`].
+    pub const fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Keepalive settings (idle timeout and pings) for event streams, see [`KeepaliveSettings`].
+    pub const fn keepalive(mut self, keepalive: KeepaliveSettings) -> Self {
+        self.keepalive = keepalive;
+        self
+    }
+
+    /// How long the [`ServiceCatalog`](crate::ServiceCatalog) is cached before it is fetched
+    /// again. Default: [`DEFAULT_CATALOG_TTL`]
+    pub const fn catalog_ttl(mut self, ttl: Duration) -> Self {
+        self.catalog_ttl = ttl;
+        self
+    }
+
+    /// How long people found by [`Webex::person_by_email()`] are cached, and how many of them at
+    /// most. A size of 0 disables the cache. Default: [`DEFAULT_PERSON_TTL`] and
+    /// [`DEFAULT_PERSON_CACHE_SIZE`]
+    pub const fn person_cache(mut self, ttl: Duration, size: usize) -> Self {
+        self.person_ttl = ttl;
+        self.person_cache_size = size;
+        self
+    }
+
+    /// Organization to fetch the [`ServiceCatalog`](crate::ServiceCatalog) for, instead of the
+    /// organization of the token's person. Useful for tokens with access to several
+    /// organizations.
+    pub fn org_id(mut self, org_id: impl Into<String>) -> Self {
+        self.org_id = Some(org_id.into());
+        self
+    }
+
+    /// Record the REST requests and event streams of the client to a cassette, or replay them
+    /// from one. See [`Cassette`].
+    pub fn cassette(mut self, cassette: Cassette) -> Self {
+        cassette.add_secret(self.token.clone());
+        self.cassette = Some(cassette);
+        self
+    }
+
+    /// Check the token with `people/me` when building the client, and fail if the token is
+    /// rejected or the WDM URL cannot be looked up. Without this (the default), a bad token only
+    /// shows up at the first request. See [`Webex::try_new()`].
+    pub const fn validate_token(mut self, validate_token: bool) -> Self {
+        self.validate_token = validate_token;
+        self
+    }
+
+    /// Creates the client.
+    ///
+    /// Unless a WDM URL was given, this looks it up in the service catalog, falling back to the
+    /// default WDM URL if that fails, unless the token is being validated.
+    ///
+    /// # Errors
+    /// * [`Error::Reqwest`] - if the HTTP client cannot be built from the given settings.
+    ///
+    /// With [`validate_token`](Self::validate_token):
+    /// * [`Error::Authentication`] - if the token is invalid or expired.
+    /// * [`Error::Other`] - if the WDM URL cannot be looked up.
+    /// * Any error of [`Webex::me()`].
+    pub async fn build(self) -> Result<Webex, Error> {
+        let web_client = if let Some(client) = self.client {
+            client
+        } else {
+            let mut builder = reqwest::Client::builder();
+            if let Some(timeout) = self.timeout {
+                builder = builder.timeout(timeout);
+            }
+            if let Some(timeout) = self.connect_timeout {
+                builder = builder.connect_timeout(timeout);
+            }
+            if let Some(user_agent) = self.user_agent {
+                builder = builder.user_agent(user_agent);
+            }
+            builder.build()?
+        };
+        let mut client = RestClient {
+            rest_host_prefix: self.rest_url,
+            web_client,
+            retry_policy: self.retry_policy,
+            cassette: self.cassette,
+            ..RestClient::new()
+        };
+
+        // Have to insert this before fetching the service catalog, since it is served by U2C
+        client
+            .host_prefix
+            .insert("limited/catalog".to_string(), self.u2c_url);
+
+        let mut webex = Webex {
+            client,
+            token: self.token,
+            mercury_url: self.mercury_url,
+            keepalive: self.keepalive,
+            people: PersonCache::new(self.person_ttl, self.person_cache_size),
+            identity: None,
+            catalog: CatalogCache::new(self.catalog_ttl, self.org_id),
+            device: DeviceData {
+                device_name: Some(DEFAULT_DEVICE_NAME.to_string()),
+                device_type: Some("DESKTOP".to_string()),
+                localized_model: Some("rust".to_string()),
+                model: Some(format!("rust-v{CRATE_VERSION}")),
+                name: Some(self.device_name),
+                system_name: Some(DEVICE_SYSTEM_NAME.to_string()),
+                system_version: Some(CRATE_VERSION.to_string()),
+                ..DeviceData::default()
+            },
+        };
+
+        if self.validate_token {
+            let me = webex.me().await?;
+            debug!(
+                "Token belongs to {} ({:?})",
+                me.display_name, me.person_type
+            );
+            webex.identity = Some(me);
+        }
+
+        let devices_url = match self.wdm_url {
+            Some(url) => url,
+            None => match webex
+                .service_url("wdm")
+                .await
+                .and_then(|url| url.ok_or_else(|| "The service catalog has no WDM URL".into()))
+            {
+                Ok(url) => {
+                    trace!("Fetched devices url {url}");
+                    url
+                }
+                Err(e) if self.validate_token => {
+                    return Err(format!("Failed to look up the WDM URL: {e}").into());
+                }
+                Err(e) => {
+                    warn!("Failed to fetch devices url, falling back to default");
+                    debug!("Error: {e:?}");
+                    DEFAULT_REGISTRATION_HOST_PREFIX.to_string()
+                }
+            },
+        };
+        webex
+            .client
+            .host_prefix
+            .insert("devices".to_string(), devices_url);
+
+        Ok(webex)
+    }
+}
+
+fn trim_url(url: String) -> String {
+    match url.strip_suffix('/') {
+        Some(trimmed) => trimmed.to_string(),
+        None => url,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn custom_endpoints() {
+        let webex = WebexBuilder::new("token")
+            .device_name("test-device")
+            .rest_url("http://localhost:1234/v1/")
+            .u2c_url("http://localhost:1234/u2c")
+            .wdm_url("http://localhost:1234/wdm")
+            .mercury_url("ws://localhost:1234/mercury")
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(webex.client.rest_host_prefix, "http://localhost:1234/v1");
+        assert_eq!(
+            webex.client.host_prefix["limited/catalog"],
+            "http://localhost:1234/u2c"
+        );
+        assert_eq!(
+            webex.client.host_prefix["devices"],
+            "http://localhost:1234/wdm"
+        );
+        assert_eq!(
+            webex.mercury_url.as_deref(),
+            Some("ws://localhost:1234/mercury")
+        );
+        assert_eq!(webex.device.name.as_deref(), Some("test-device"));
+        assert_eq!(webex.token_kind(), None);
+    }
+
+    #[tokio::test]
+    async fn validates_token() {
+        let (prefix, _) = crate::tests::mock_server(vec![
+            (
+                "401 Unauthorized",
+                "",
+                r#"{"message":"The request requires a valid access token set in the Authorization request header."}"#.to_string(),
+            ),
+            (
+                "200 OK",
+                "",
+                r#"{"id":"b","displayName":"Bot","type":"bot"}"#.to_string(),
+            ),
+        ])
+        .await;
+        let builder = WebexBuilder::new("token")
+            .rest_url(prefix)
+            .wdm_url("http://localhost")
+            .retry_policy(RetryPolicy::disabled())
+            .validate_token(true);
+        let err = builder.clone().build().await.err().unwrap();
+        assert!(
+            matches!(&err, Error::Authentication(Some(error)) if error.message.as_deref().is_some_and(|message| message.starts_with("The request requires a valid access token"))),
+            "{err:?}"
+        );
+        let webex = builder.build().await.unwrap();
+        assert_eq!(webex.token_kind(), Some(crate::TokenKind::Bot));
+        assert_eq!(webex.identity().unwrap().display_name, "Bot");
+    }
+}
@@ -0,0 +1,701 @@
+#![deny(missing_docs)]
+//! Recording REST interactions and event streams to a cassette file, and replaying them
+
+use crate::error::Error;
+use base64::Engine;
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, trace, warn};
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard, OnceLock, PoisonError, Weak,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{net::TcpListener, sync::Notify};
+use tokio_tungstenite::tungstenite::{
+    protocol::{frame::coding::CloseCode, CloseFrame},
+    Message as TMessage,
+};
+
+/// Replaces secrets in a cassette.
+const REDACTED: &str = "REDACTED";
+
+/// How long changes to a recording are collected before the file is written again.
+const WRITE_DELAY: Duration = Duration::from_millis(100);
+
+/// Whether a [`Cassette`] records or replays.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CassetteMode {
+    /// Requests go to Webex, and are written to the cassette along with their responses and the
+    /// frames of event streams.
+    Record,
+    /// Nothing goes to Webex: responses and event streams are served from the cassette.
+    Replay,
+}
+
+/// A file of recorded REST interactions and event streams, set with
+/// [`WebexBuilder::cassette()`](crate::WebexBuilder::cassette).
+///
+/// When recording, each request made through the client is written to the file with its
+/// response, and each event stream with the frames it received. The token of the client, and
+/// any other secret given to [`redact()`](Self::redact), is replaced with `REDACTED`, as are
+/// `Authorization` headers. Request bodies that are streamed, such as file uploads, aren't
+/// recorded; the request is marked with `"streamedBody": true` instead.
+///
+/// The file is written in the background shortly after each change. Changes still pending when
+/// the last clone of the cassette is dropped are written from a blocking task, which may not get
+/// to run if the runtime is shutting down: call [`flush()`](Self::flush) once recording is done
+/// to be sure the file is complete.
+///
+/// The offset of a frame is the time it was read from the
+/// [`WebexEventStream`](crate::WebexEventStream), not when it arrived on the socket: frames that
+/// arrive while the program is busy elsewhere are recorded (and replayed) as arriving once it
+/// gets to them.
+///
+/// When replaying, each request is answered with the first recorded response to the same method
+/// and URL that hasn't been used yet, or fails with [`Error::Other`] if there is none. Each event
+/// stream is served by a local websocket, which sends the frames of the next recorded stream at
+/// the same offsets from its authorization as they were received. Once the frames run out the
+/// connection stays open, unless the recording ended with the connection being closed.
+///
+/// ```no_run
+/// # async fn example() -> Result<(), webex::error::Error> {
+/// use webex::{Cassette, Webex};
+///
+/// let record = std::env::var("RECORD").is_ok();
+/// let cassette = if record {
+///     Cassette::record("tests/cassettes/echo.json")
+/// } else {
+///     Cassette::replay("tests/cassettes/echo.json")?
+/// };
+/// let token = std::env::var("WEBEX_TOKEN").unwrap_or_default();
+/// let webex = Webex::builder(&token).cassette(cassette).build().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Cassette {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    path: PathBuf,
+    mode: CassetteMode,
+    secrets: Mutex<Vec<String>>,
+    recording: Mutex<Recording>,
+    /// In replay mode, which interactions have been served, and how many streams.
+    used: Mutex<(Vec<bool>, usize)>,
+    /// In record mode, wakes the task writing the file. Started on the first change.
+    changed: OnceLock<Arc<Notify>>,
+    /// Held while writing the file, so that writes happen in order.
+    writing: tokio::sync::Mutex<()>,
+    /// Whether the recording changed since the file was last written.
+    unsaved: AtomicBool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Recording {
+    #[serde(default)]
+    interactions: Vec<Interaction>,
+    #[serde(default)]
+    streams: Vec<StreamRecording>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RecordedRequest {
+    method: String,
+    url: String,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(flatten)]
+    body: Payload,
+    /// Set when the body was streamed, so it isn't in `body`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    streamed_body: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RecordedResponse {
+    status: u16,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(flatten)]
+    body: Payload,
+}
+
+/// A body or websocket payload: as text if it is UTF-8, otherwise as base64.
+#[skip_serializing_none]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct Payload {
+    body: Option<String>,
+    body_base64: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StreamRecording {
+    url: String,
+    #[serde(default)]
+    frames: Vec<RecordedFrame>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RecordedFrame {
+    /// Time from the authorization of the stream until the frame was read from it.
+    offset_ms: u64,
+    #[serde(flatten)]
+    message: FrameMessage,
+}
+
+#[skip_serializing_none]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum FrameMessage {
+    Text {
+        #[serde(flatten)]
+        payload: Payload,
+    },
+    Binary {
+        #[serde(flatten)]
+        payload: Payload,
+    },
+    Close {
+        code: Option<u16>,
+        reason: Option<String>,
+    },
+}
+
+impl Payload {
+    fn new(bytes: &[u8], redact: impl Fn(&str) -> String) -> Self {
+        match std::str::from_utf8(bytes) {
+            Ok("") => Self::default(),
+            Ok(text) => Self {
+                body: Some(redact(text)),
+                body_base64: None,
+            },
+            Err(_) => Self {
+                body: None,
+                body_base64: Some(base64::engine::general_purpose::STANDARD.encode(bytes)),
+            },
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        match (&self.body, &self.body_base64) {
+            (Some(text), _) => text.clone().into_bytes(),
+            (None, Some(encoded)) => base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .unwrap_or_default(),
+            (None, None) => Vec::new(),
+        }
+    }
+}
+
+impl RecordedFrame {
+    fn message(&self) -> TMessage {
+        match &self.message {
+            FrameMessage::Text { payload } => {
+                TMessage::Text(String::from_utf8_lossy(&payload.bytes()).into_owned())
+            }
+            FrameMessage::Binary { payload } => TMessage::Binary(payload.bytes()),
+            FrameMessage::Close { code, reason } => TMessage::Close(code.map(|code| CloseFrame {
+                code: CloseCode::from(code),
+                reason: reason.clone().unwrap_or_default().into(),
+            })),
+        }
+    }
+}
+
+impl Cassette {
+    /// A cassette that records to `path`, replacing the file if it exists.
+    pub fn record(path: impl Into<PathBuf>) -> Self {
+        Self::new(path.into(), CassetteMode::Record, Recording::default())
+    }
+
+    /// A cassette that replays the recording in `path`.
+    ///
+    /// # Errors
+    /// * [`Error::Io`] - if the file cannot be read.
+    /// * [`Error::Json`] - if the file is not a cassette.
+    pub fn replay(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+        let recording = serde_json::from_slice(&std::fs::read(&path)?)?;
+        Ok(Self::new(path, CassetteMode::Replay, recording))
+    }
+
+    fn new(path: PathBuf, mode: CassetteMode, recording: Recording) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                path,
+                mode,
+                secrets: Mutex::new(Vec::new()),
+                used: Mutex::new((vec![false; recording.interactions.len()], 0)),
+                recording: Mutex::new(recording),
+                changed: OnceLock::new(),
+                writing: tokio::sync::Mutex::new(()),
+                unsaved: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Also replace this secret (e.g. an OAuth client secret) with `REDACTED` when recording.
+    /// The token of the client is always redacted.
+    #[must_use]
+    pub fn redact(self, secret: impl Into<String>) -> Self {
+        self.add_secret(secret.into());
+        self
+    }
+
+    /// Whether this cassette records or replays.
+    #[must_use]
+    pub fn mode(&self) -> CassetteMode {
+        self.inner.mode
+    }
+
+    /// Number of recorded REST interactions.
+    #[must_use]
+    pub fn interactions(&self) -> usize {
+        lock(&self.inner.recording).interactions.len()
+    }
+
+    /// Number of recorded event streams.
+    #[must_use]
+    pub fn streams(&self) -> usize {
+        lock(&self.inner.recording).streams.len()
+    }
+
+    /// Write the recording to the file now, instead of waiting for the background write.
+    /// Does nothing when replaying.
+    ///
+    /// # Errors
+    /// * [`Error::Io`] - if the file cannot be written.
+    pub async fn flush(&self) -> Result<(), Error> {
+        self.inner.write().await
+    }
+
+    pub(crate) fn add_secret(&self, secret: String) {
+        if !secret.is_empty() {
+            lock(&self.inner.secrets).push(secret);
+        }
+    }
+
+    pub(crate) fn is_replaying(&self) -> bool {
+        self.inner.mode == CassetteMode::Replay
+    }
+
+    fn redact_text(&self, text: &str) -> String {
+        lock(&self.inner.secrets)
+            .iter()
+            .fold(text.to_string(), |text, secret| {
+                text.replace(secret.as_str(), REDACTED)
+            })
+    }
+
+    fn headers(&self, headers: &reqwest::header::HeaderMap) -> BTreeMap<String, String> {
+        headers
+            .iter()
+            .map(|(name, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes());
+                let value = if name == reqwest::header::AUTHORIZATION {
+                    // Keep the scheme, e.g. "Bearer REDACTED"
+                    value.split_once(' ').map_or_else(
+                        || REDACTED.to_string(),
+                        |(scheme, _)| format!("{scheme} {REDACTED}"),
+                    )
+                } else {
+                    self.redact_text(&value)
+                };
+                (name.to_string(), value)
+            })
+            .collect()
+    }
+
+    /// Send a request (or not, when replaying), recording the response.
+    pub(crate) async fn send(
+        &self,
+        request_builder: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, Error> {
+        let (client, request) = request_builder.build_split();
+        let request = request?;
+        let body = request.body().map(reqwest::Body::as_bytes);
+        let recorded = RecordedRequest {
+            method: request.method().to_string(),
+            url: self.redact_text(request.url().as_str()),
+            headers: self.headers(request.headers()),
+            body: Payload::new(body.flatten().unwrap_or_default(), |text| {
+                self.redact_text(text)
+            }),
+            streamed_body: body.is_some_and(|body| body.is_none()),
+        };
+        if recorded.streamed_body && self.inner.mode == CassetteMode::Record {
+            debug!(
+                "Not recording the streamed body of {} {}",
+                recorded.method, recorded.url
+            );
+        }
+        let response = match self.inner.mode {
+            CassetteMode::Replay => self.take_interaction(&recorded)?,
+            CassetteMode::Record => {
+                let response = client.execute(request).await?;
+                let status = response.status().as_u16();
+                let headers = self.headers(response.headers());
+                let body = response.bytes().await?;
+                let response = RecordedResponse {
+                    status,
+                    headers,
+                    body: Payload::new(&body, |text| self.redact_text(text)),
+                };
+                trace!("Recording {} {}", recorded.method, recorded.url);
+                lock(&self.inner.recording).interactions.push(Interaction {
+                    request: recorded,
+                    response: response.clone(),
+                });
+                self.save();
+                // Serve the body as received, not as redacted
+                return to_response(&response, body.to_vec());
+            }
+        };
+        to_response(&response, response.body.bytes())
+    }
+
+    fn take_interaction(&self, request: &RecordedRequest) -> Result<RecordedResponse, Error> {
+        let recording = lock(&self.inner.recording);
+        let mut used = lock(&self.inner.used);
+        let found = recording
+            .interactions
+            .iter()
+            .zip(used.0.iter_mut())
+            .find(|(interaction, used)| {
+                !**used
+                    && interaction.request.method == request.method
+                    && interaction.request.url == request.url
+            })
+            .map(|(interaction, used)| {
+                *used = true;
+                interaction.response.clone()
+            });
+        drop(used);
+        drop(recording);
+        trace!("Replaying {} {}", request.method, request.url);
+        found.ok_or_else(|| {
+            format!(
+                "No recorded response left for {} {}",
+                request.method, request.url
+            )
+            .into()
+        })
+    }
+
+    /// Start recording an event stream connected to `url`.
+    pub(crate) fn record_stream(&self, url: &str) -> StreamRecorder {
+        let mut recording = lock(&self.inner.recording);
+        recording.streams.push(StreamRecording {
+            url: self.redact_text(url),
+            frames: Vec::new(),
+        });
+        let stream = recording.streams.len() - 1;
+        drop(recording);
+        self.save();
+        StreamRecorder {
+            cassette: self.clone(),
+            stream,
+            started: Instant::now(),
+        }
+    }
+
+    /// Serve the next recorded event stream on a local websocket, returning its URL.
+    pub(crate) async fn replay_stream(&self) -> Result<String, Error> {
+        let stream = {
+            let mut used = lock(&self.inner.used);
+            let index = used.1;
+            used.1 += 1;
+            drop(used);
+            lock(&self.inner.recording).streams.get(index).cloned()
+        };
+        let stream = stream.ok_or("No recorded event stream left")?;
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("ws://{}", listener.local_addr()?);
+        debug!("Replaying event stream of {} on {url}", stream.url);
+        tokio::spawn(async move {
+            if let Err(e) = serve_stream(listener, stream.frames).await {
+                debug!("Replayed event stream ended: {e}");
+            }
+        });
+        Ok(url)
+    }
+
+    /// Have the recording written to the file soon, without blocking the caller.
+    fn save(&self) {
+        self.inner.unsaved.store(true, Ordering::Release);
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            // Written when dropped instead
+            return;
+        };
+        let changed = self.inner.changed.get_or_init(|| {
+            let changed = Arc::new(Notify::new());
+            runtime.spawn(write_behind(Arc::downgrade(&self.inner), changed.clone()));
+            changed
+        });
+        changed.notify_one();
+    }
+}
+
+impl Inner {
+    fn json(&self) -> Result<Vec<u8>, Error> {
+        Ok(serde_json::to_vec_pretty(&*lock(&self.recording))?)
+    }
+
+    async fn write(&self) -> Result<(), Error> {
+        if self.mode == CassetteMode::Replay {
+            return Ok(());
+        }
+        let _writing = self.writing.lock().await;
+        self.unsaved.store(false, Ordering::Release);
+        tokio::fs::write(&self.path, self.json()?).await?;
+        Ok(())
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if self.mode != CassetteMode::Record || !*self.unsaved.get_mut() {
+            return;
+        }
+        let path = self.path.clone();
+        let write = move |json: Result<Vec<u8>, Error>| {
+            if let Err(e) = json.and_then(|json| std::fs::write(&path, json).map_err(Error::from)) {
+                warn!("Failed to write cassette {}: {e}", path.display());
+            }
+        };
+        let json = self.json();
+        match tokio::runtime::Handle::try_current() {
+            // Don't block a runtime thread on the file system
+            Ok(runtime) => drop(runtime.spawn_blocking(move || write(json))),
+            Err(_) => write(json),
+        }
+    }
+}
+
+/// Writes the recording whenever it changed, at most once per [`WRITE_DELAY`], until the
+/// cassette is dropped.
+async fn write_behind(inner: Weak<Inner>, changed: Arc<Notify>) {
+    loop {
+        changed.notified().await;
+        tokio::time::sleep(WRITE_DELAY).await;
+        let Some(inner) = inner.upgrade() else {
+            return;
+        };
+        if let Err(e) = inner.write().await {
+            warn!("Failed to write cassette {}: {e}", inner.path.display());
+        }
+    }
+}
+
+/// Records the frames received by a [`WebexEventStream`](crate::WebexEventStream).
+pub struct StreamRecorder {
+    cassette: Cassette,
+    stream: usize,
+    started: Instant,
+}
+
+impl StreamRecorder {
+    /// Record a frame that was just read. Pings and pongs are not recorded, since the replaying
+    /// websocket answers pings by itself.
+    pub fn record(&self, message: &TMessage) {
+        let redact = |text: &str| self.cassette.redact_text(text);
+        let message = match message {
+            TMessage::Text(text) => FrameMessage::Text {
+                payload: Payload::new(text.as_bytes(), redact),
+            },
+            TMessage::Binary(bytes) => FrameMessage::Binary {
+                payload: Payload::new(bytes, redact),
+            },
+            TMessage::Close(frame) => FrameMessage::Close {
+                code: frame.as_ref().map(|frame| frame.code.into()),
+                reason: frame.as_ref().map(|frame| frame.reason.to_string()),
+            },
+            TMessage::Ping(_) | TMessage::Pong(_) | TMessage::Frame(_) => return,
+        };
+        let offset_ms = u64::try_from(self.started.elapsed().as_millis()).unwrap_or(u64::MAX);
+        if let Some(stream) = lock(&self.cassette.inner.recording)
+            .streams
+            .get_mut(self.stream)
+        {
+            stream.frames.push(RecordedFrame { offset_ms, message });
+        }
+        self.cassette.save();
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn to_response(response: &RecordedResponse, body: Vec<u8>) -> Result<reqwest::Response, Error> {
+    let mut builder = http::Response::builder().status(response.status);
+    for (name, value) in &response.headers {
+        // The body is served whole, whatever its encoding was
+        if name != "content-length" && name != "transfer-encoding" && name != "content-encoding" {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+    }
+    builder
+        .body(body)
+        .map(reqwest::Response::from)
+        .map_err(|e| format!("Invalid recorded response: {e}").into())
+}
+
+/// Accept one connection, answer its authorization like mercury does, then send the frames at
+/// their offsets while answering pings, until the client goes away.
+async fn serve_stream(listener: TcpListener, frames: Vec<RecordedFrame>) -> Result<(), Error> {
+    let (socket, _) = listener.accept().await?;
+    let closed = |e: tokio_tungstenite::tungstenite::Error| Error::Closed(e.to_string());
+    let mut ws = tokio_tungstenite::accept_async(socket)
+        .await
+        .map_err(closed)?;
+    match ws.next().await {
+        Some(Ok(TMessage::Text(_))) => {}
+        _ => return Err("Expected an authorization message".into()),
+    }
+    ws.send(TMessage::Pong(Vec::new())).await.map_err(closed)?;
+    let started = tokio::time::Instant::now();
+    let mut frames = frames.into_iter();
+    let mut next = frames.next();
+    loop {
+        let deadline = next
+            .as_ref()
+            .map(|frame| started + Duration::from_millis(frame.offset_ms));
+        tokio::select! {
+            () = async {
+                match deadline {
+                    Some(deadline) => tokio::time::sleep_until(deadline).await,
+                    None => std::future::pending().await,
+                }
+            } => {
+                if let Some(frame) = next.take() {
+                    ws.send(frame.message()).await.map_err(closed)?;
+                }
+                next = frames.next();
+            }
+            incoming = ws.next() => match incoming {
+                Some(Ok(TMessage::Close(_)) | Err(_)) | None => return Ok(()),
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::{devices_reply, event_message, mock_server, mock_websocket},
+        FileUpload, MessageOut, Person, Webex,
+    };
+
+    #[tokio::test]
+    async fn records_and_replays() {
+        let dir = std::env::temp_dir().join(format!("webex-cassette-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cassette.json");
+
+        let ws_url = mock_websocket(
+            vec![vec![
+                event_message("conversation.activity"),
+                event_message("status.start_typing"),
+            ]],
+            true,
+        )
+        .await;
+        let (prefix, requests) = mock_server(vec![
+            (
+                "200 OK",
+                "",
+                r#"{"id":"me","displayName":"Secret token: s3cret","type":"bot"}"#.to_string(),
+            ),
+            ("200 OK", "", devices_reply(&ws_url)),
+            ("200 OK", "", r#"{"id":"m"}"#.to_string()),
+        ])
+        .await;
+        let cassette = Cassette::record(&path).redact("client-secret");
+        let webex = Webex::builder("s3cret")
+            .rest_url(&prefix)
+            .wdm_url(&prefix)
+            .cassette(cassette.clone())
+            .build()
+            .await
+            .unwrap();
+        let me = webex.me().await.unwrap();
+        let mut events = webex.event_stream().await.unwrap();
+        let first = events.next().await.unwrap();
+        // Offsets are taken when frames are read, so this delay is replayed too
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let second = events.next().await.unwrap();
+        let recorded = (me, first, second);
+        assert_eq!(recorded.0.display_name, "Secret token: s3cret");
+        let upload = MessageOut {
+            room_id: Some("r".to_string()),
+            ..MessageOut::default()
+        };
+        webex
+            .send_message_with_file(&upload, FileUpload::from_bytes("a.txt", "contents"))
+            .await
+            .unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 3);
+
+        // Written in the background by now
+        tokio::time::sleep(WRITE_DELAY * 3).await;
+        let file = std::fs::read_to_string(&path).unwrap();
+        assert!(!file.contains("s3cret"), "{file}");
+        assert!(file.contains("Bearer REDACTED"));
+        assert!(file.contains("start_typing"));
+        assert!(file.contains(r#""streamedBody": true"#), "{file}");
+        assert!(!file.contains("contents"), "{file}");
+        drop((events, webex));
+        cassette.flush().await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), file);
+        drop(cassette);
+
+        // Nothing is listening any more, so this can only work from the cassette
+        let cassette = Cassette::replay(&path).unwrap();
+        assert_eq!((cassette.interactions(), cassette.streams()), (3, 1));
+        let webex = Webex::builder("another-token")
+            .rest_url(&prefix)
+            .wdm_url(&prefix)
+            .cassette(cassette)
+            .build()
+            .await
+            .unwrap();
+        let me: Person = webex.me().await.unwrap();
+        assert_eq!(me.display_name, "Secret token: REDACTED");
+        let mut events = webex.event_stream().await.unwrap();
+        let started = Instant::now();
+        assert_eq!(events.next().await.unwrap(), recorded.1);
+        assert_eq!(events.next().await.unwrap(), recorded.2);
+        // The second event was read after the first and the sleep
+        assert!(started.elapsed() >= Duration::from_millis(150));
+
+        let err = webex.me().await.unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("No recorded response left for GET"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,250 @@
+#![deny(missing_docs)]
+//! The U2C service catalog, mapping Webex service names to their URLs
+
+use crate::{error::Error, AuthorizationType, Webex};
+use log::{trace, warn};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+
+/// How long a fetched catalog is used before it is fetched again, unless configured with
+/// [`WebexBuilder::catalog_ttl()`](crate::WebexBuilder::catalog_ttl).
+pub const DEFAULT_CATALOG_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// URLs of the Webex services available to an organization, such as `wdm` (device registration)
+/// or `atlas`. Fetched and cached by [`Webex::service_catalog()`].
+#[derive(Clone, Debug)]
+pub struct ServiceCatalog {
+    org_id: String,
+    services: HashMap<String, String>,
+    fetched_at: Instant,
+}
+
+impl ServiceCatalog {
+    /// URL of a service, by name.
+    #[must_use]
+    pub fn get(&self, service: &str) -> Option<&str> {
+        self.services.get(service).map(String::as_str)
+    }
+
+    /// All services, by name.
+    #[must_use]
+    pub const fn services(&self) -> &HashMap<String, String> {
+        &self.services
+    }
+
+    /// The organization the catalog was fetched for.
+    #[must_use]
+    pub fn org_id(&self) -> &str {
+        &self.org_id
+    }
+
+    /// Time since the catalog was fetched.
+    #[must_use]
+    pub fn age(&self) -> Duration {
+        self.fetched_at.elapsed()
+    }
+}
+
+/// Per-client state behind [`Webex::service_catalog()`], shared between clones.
+#[derive(Debug)]
+pub struct CatalogCache {
+    ttl: Duration,
+    org_id: Option<String>,
+    current: Mutex<Option<ServiceCatalog>>,
+}
+
+impl CatalogCache {
+    pub fn new(ttl: Duration, org_id: Option<String>) -> Arc<Self> {
+        Arc::new(Self {
+            ttl,
+            org_id,
+            current: Mutex::new(None),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CatalogReply {
+    #[serde(default)]
+    service_links: HashMap<String, serde_json::Value>,
+}
+
+impl Webex {
+    /// The service catalog of the caller's organization. It is fetched on first use and again
+    /// once it is older than the configured TTL; if fetching it again fails, the stale catalog is
+    /// returned. Failures are not cached.
+    ///
+    /// The organization is the one given to
+    /// [`WebexBuilder::org_id()`](crate::WebexBuilder::org_id), or else the one the token's
+    /// person belongs to.
+    ///
+    /// # Errors
+    /// * [`Error::Other`] - if the person has no organization.
+    /// * Errors of [`Webex::me()`] and of the catalog request, if there is no catalog to fall back
+    ///   to.
+    pub async fn service_catalog(&self) -> Result<ServiceCatalog, Error> {
+        // Held while fetching, so concurrent callers wait for one fetch instead of each fetching
+        let mut current = self.catalog.current.lock().await;
+        let result = match current.as_ref() {
+            Some(catalog) if catalog.age() < self.catalog.ttl => {
+                trace!("Using cached service catalog");
+                Ok(catalog.clone())
+            }
+            _ => match self.fetch_service_catalog(current.as_ref()).await {
+                Ok(catalog) => Ok(current.insert(catalog).clone()),
+                Err(e) => match current.as_ref() {
+                    Some(stale) => {
+                        warn!("Failed to refresh service catalog, using stale one: {e}");
+                        Ok(stale.clone())
+                    }
+                    None => Err(e),
+                },
+            },
+        };
+        drop(current);
+        result
+    }
+
+    /// Fetch the service catalog again, regardless of its age.
+    ///
+    /// # Errors
+    /// As for [`service_catalog()`](Self::service_catalog), without falling back to the old
+    /// catalog.
+    pub async fn refresh_service_catalog(&self) -> Result<ServiceCatalog, Error> {
+        let mut current = self.catalog.current.lock().await;
+        let catalog = self.fetch_service_catalog(current.as_ref()).await?;
+        Ok(current.insert(catalog).clone())
+    }
+
+    /// URL of a service in the [service catalog](Self::service_catalog), or `None` if the
+    /// organization doesn't have that service.
+    ///
+    /// # Errors
+    /// As for [`service_catalog()`](Self::service_catalog).
+    pub async fn service_url(&self, service: &str) -> Result<Option<String>, Error> {
+        Ok(self
+            .service_catalog()
+            .await?
+            .get(service)
+            .map(str::to_string))
+    }
+
+    async fn fetch_service_catalog(
+        &self,
+        previous: Option<&ServiceCatalog>,
+    ) -> Result<ServiceCatalog, Error> {
+        let org_id = match (&self.catalog.org_id, previous, &self.identity) {
+            (Some(org_id), _, _) => org_id.clone(),
+            (None, Some(previous), _) => previous.org_id.clone(),
+            (None, None, Some(me)) => me.org_id.clone(),
+            (None, None, None) => self.me().await?.org_id,
+        };
+        if org_id.is_empty() {
+            return Err("Can't get the service catalog without an organization".into());
+        }
+        let params = [("format", "hostmap"), ("orgId", org_id.as_str())];
+        let reply: CatalogReply = self
+            .client
+            .api_get(
+                "limited/catalog",
+                Some(params),
+                AuthorizationType::Bearer(&self.token),
+            )
+            .await?;
+        let services = reply
+            .service_links
+            .into_iter()
+            .filter_map(|(name, url)| match url {
+                serde_json::Value::String(url) => Some((name, url)),
+                _ => None,
+            })
+            .collect();
+        trace!("Fetched service catalog for {org_id}: {services:?}");
+        Ok(ServiceCatalog {
+            org_id,
+            services,
+            fetched_at: Instant::now(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{retry::RetryPolicy, tests::mock_server};
+
+    const CATALOG: &str = r#"{"serviceLinks":{"wdm":"https://wdm.example.com/wdm/api/v1","atlas":"https://atlas.example.com","newService":{"nested":true}},"format":"hostmap"}"#;
+
+    #[tokio::test]
+    async fn fetches_refreshes_and_falls_back() {
+        let (prefix, requests) = mock_server(vec![
+            (
+                "200 OK",
+                "",
+                r#"{"id":"me","orgId":"org2","type":"bot"}"#.to_string(),
+            ),
+            ("200 OK", "", CATALOG.to_string()),
+            ("200 OK", "", r#"{"serviceLinks":{}}"#.to_string()),
+            ("503 Service Unavailable", "", String::new()),
+        ])
+        .await;
+        let webex = Webex::builder("token")
+            .rest_url(&prefix)
+            .u2c_url(&prefix)
+            .retry_policy(RetryPolicy::disabled())
+            .catalog_ttl(Duration::ZERO)
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(
+            webex.client.host_prefix["devices"],
+            "https://wdm.example.com/wdm/api/v1"
+        );
+
+        let catalog = webex.refresh_service_catalog().await.unwrap();
+        assert_eq!(catalog.org_id(), "org2");
+        assert_eq!(catalog.get("wdm"), None);
+        // The refresh fails, so the previous catalog is used
+        assert_eq!(webex.service_url("atlas").await.unwrap(), None);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                "GET /people/me HTTP/1.1",
+                "GET /limited/catalog?format=hostmap&orgId=org2 HTTP/1.1",
+                "GET /limited/catalog?format=hostmap&orgId=org2 HTTP/1.1",
+                "GET /limited/catalog?format=hostmap&orgId=org2 HTTP/1.1",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn tolerant_parsing_and_explicit_org() {
+        let (prefix, requests) = mock_server(vec![("200 OK", "", CATALOG.to_string())]).await;
+        let webex = Webex::builder("token")
+            .rest_url(&prefix)
+            .u2c_url(&prefix)
+            .wdm_url("http://localhost")
+            .org_id("org1")
+            .build()
+            .await
+            .unwrap();
+        let catalog = webex.service_catalog().await.unwrap();
+        assert_eq!(catalog.get("atlas"), Some("https://atlas.example.com"));
+        assert_eq!(catalog.services().len(), 2);
+        // Cached
+        assert_eq!(
+            webex.clone().service_url("wdm").await.unwrap().as_deref(),
+            Some("https://wdm.example.com/wdm/api/v1")
+        );
+        assert_eq!(
+            *requests.lock().unwrap(),
+            ["GET /limited/catalog?format=hostmap&orgId=org1 HTTP/1.1"]
+        );
+    }
+}
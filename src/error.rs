@@ -1,42 +1,219 @@
+use crate::retry::is_transient;
 use reqwest::StatusCode;
+use std::{fmt, sync::Arc, time::Duration};
+use tokio_tungstenite::tungstenite::Error as WsError;
 
-#[derive(thiserror::Error, Debug)]
+/// Errors returned by this crate.
+///
+/// Errors from other crates are kept behind an [`Arc`], so that `Error` can be cloned.
+#[derive(thiserror::Error, Clone, Debug)]
+#[non_exhaustive]
 pub enum Error {
     // Foreign errors
     #[error("IO error: {0}")]
-    Io(#[from] std::io::Error),
+    Io(Arc<std::io::Error>),
     #[error("JSON error: {0}")]
-    Json(#[from] serde_json::error::Error),
+    Json(Arc<serde_json::error::Error>),
     #[error("URL form encoding error: {0}")]
-    FormEncoding(#[from] serde_html_form::ser::Error),
+    FormEncoding(Arc<serde_html_form::ser::Error>),
     #[error("UTF8 error: {0}")]
     UTF8(#[from] std::str::Utf8Error),
 
     #[error("reqwest error: {0}")]
-    Reqwest(#[from] reqwest::Error),
+    Reqwest(Arc<reqwest::Error>),
 
     // WS/request errors
+    /// A request, or the event stream, timed out.
+    #[error("Timed out: {0}")]
+    Timeout(String),
+    /// The websocket of an event stream was closed.
     #[error("Connection was closed: {0}")]
     Closed(String),
-    #[error("HTTP Status: '{0}'")]
-    Status(StatusCode),
-    #[error("HTTP Status: '{0}' Message: {1}")]
-    StatusText(StatusCode, String),
-    #[error("{0} Retry in: '{1:?}'")]
-    Limited(StatusCode, Option<i64>),
-    #[error("{0} {1}")]
-    Tungstenite(tokio_tungstenite::tungstenite::Error, String),
-    #[error("Webex API changed: {0}")]
-    Api(&'static str),
+    #[error("{1}: {0}")]
+    Tungstenite(Arc<tokio_tungstenite::tungstenite::Error>, String),
 
-    #[error("Authentication error")]
-    Authentication,
+    /// The token was rejected (HTTP 401), an OAuth flow failed, or a webhook signature didn't
+    /// match. Carries the API response, if there was one.
+    #[error("Authentication failed{}", display_cause(.0.as_deref()))]
+    Authentication(Option<Box<ApiError>>),
+    /// The resource doesn't exist, or isn't visible to the caller (HTTP 404).
+    #[error("Not found: {0}")]
+    NotFound(Box<ApiError>),
+    /// Too many requests (HTTP 429), or the resource is locked (HTTP 423). See
+    /// [`retry_after()`](Self::retry_after).
+    #[error("Throttled: {0}")]
+    Throttled(Box<ApiError>),
+    /// Any other non-2xx response.
+    #[error("API error: {0}")]
+    Api(Box<ApiError>),
+    /// A response or event didn't have the expected shape.
+    #[error("Webex API changed: {0}")]
+    UnexpectedResponse(&'static str),
+    /// The request would have sent the token to a URL outside the Webex hosts and the
+    /// configured REST URL, so it wasn't made.
+    #[error("Refusing to send credentials to {0}")]
+    UntrustedUrl(String),
 
     // catch-all
     #[error("Unknown error: {0}")]
     Other(String),
 }
 
+/// A non-2xx response from the Webex API.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ApiError {
+    /// HTTP status of the response.
+    pub status: StatusCode,
+    /// Description of the error given by the API.
+    pub message: Option<String>,
+    /// ID of the request, for Webex support.
+    pub tracking_id: Option<String>,
+    /// The response body, if it was JSON.
+    pub body: Option<serde_json::Value>,
+    /// The `Retry-After` header.
+    pub retry_after: Option<Duration>,
+}
+
+impl ApiError {
+    /// An error with only a status code.
+    #[must_use]
+    pub const fn new(status: StatusCode) -> Self {
+        Self {
+            status,
+            message: None,
+            tracking_id: None,
+            body: None,
+            retry_after: None,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HTTP {}", self.status)?;
+        if let Some(message) = &self.message {
+            write!(f, ": {message}")?;
+        }
+        if let Some(tracking_id) = &self.tracking_id {
+            write!(f, " (trackingId: {tracking_id})")?;
+        }
+        Ok(())
+    }
+}
+
+/// IO errors caused by the connection going away, rather than by e.g. a bad address.
+fn is_transient_io(error: &std::io::Error) -> bool {
+    matches!(
+        error.kind(),
+        std::io::ErrorKind::ConnectionRefused
+            | std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::ConnectionAborted
+            | std::io::ErrorKind::BrokenPipe
+            | std::io::ErrorKind::TimedOut
+            | std::io::ErrorKind::UnexpectedEof
+    )
+}
+
+fn display_cause(cause: Option<&ApiError>) -> String {
+    cause.map_or_else(String::new, |cause| format!(": {cause}"))
+}
+
+impl Error {
+    /// Whether the operation may succeed if it is tried again: throttling, timeouts, closed
+    /// connections, connection failures and 5xx responses that are usually transient. Websocket
+    /// errors only count if the connection was lost or the handshake got a 5xx, not e.g. for a bad
+    /// URL, a TLS failure or a protocol violation.
+    #[must_use]
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Throttled(_) | Self::Timeout(_) | Self::Closed(_) => true,
+            Self::Api(error) => is_transient(error.status),
+            Self::Reqwest(error) => error.is_connect(),
+            Self::Io(error) => is_transient_io(error),
+            Self::Tungstenite(error, _) => match &**error {
+                WsError::ConnectionClosed | WsError::AlreadyClosed => true,
+                WsError::Io(error) => is_transient_io(error),
+                WsError::Http(response) => response.status().is_server_error(),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// How long the API asked to wait before trying again, when throttled.
+    #[must_use]
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::Throttled(error) => error.retry_after,
+            _ => None,
+        }
+    }
+
+    /// The API response, for errors caused by a non-2xx response.
+    #[must_use]
+    pub fn api_error(&self) -> Option<&ApiError> {
+        match self {
+            Self::Authentication(Some(error))
+            | Self::NotFound(error)
+            | Self::Throttled(error)
+            | Self::Api(error) => Some(error),
+            _ => None,
+        }
+    }
+
+    /// HTTP status, for errors caused by a non-2xx response.
+    #[must_use]
+    pub fn status(&self) -> Option<StatusCode> {
+        self.api_error().map(|error| error.status)
+    }
+
+    /// Webex tracking ID of the failed request, if the API returned one.
+    #[must_use]
+    pub fn tracking_id(&self) -> Option<&str> {
+        self.api_error()?.tracking_id.as_deref()
+    }
+}
+
+impl From<ApiError> for Error {
+    fn from(error: ApiError) -> Self {
+        let error = Box::new(error);
+        match error.status {
+            StatusCode::UNAUTHORIZED => Self::Authentication(Some(error)),
+            StatusCode::NOT_FOUND => Self::NotFound(error),
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::LOCKED => Self::Throttled(error),
+            _ => Self::Api(error),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(Arc::new(error))
+    }
+}
+
+impl From<serde_json::error::Error> for Error {
+    fn from(error: serde_json::error::Error) -> Self {
+        Self::Json(Arc::new(error))
+    }
+}
+
+impl From<serde_html_form::ser::Error> for Error {
+    fn from(error: serde_html_form::ser::Error) -> Self {
+        Self::FormEncoding(Arc::new(error))
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            Self::Timeout(error.to_string())
+        } else {
+            Self::Reqwest(Arc::new(error))
+        }
+    }
+}
+
 impl From<String> for Error {
     fn from(s: String) -> Self {
         Error::Other(s)
@@ -47,3 +224,61 @@ impl From<&str> for Error {
         Error::Other(s.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classification() {
+        let throttled = Error::from(ApiError {
+            retry_after: Some(Duration::from_secs(30)),
+            tracking_id: Some("ROUTER_1".to_string()),
+            ..ApiError::new(StatusCode::TOO_MANY_REQUESTS)
+        });
+        assert!(throttled.is_retryable());
+        assert_eq!(throttled.retry_after(), Some(Duration::from_secs(30)));
+        let cloned = throttled.clone();
+        assert_eq!(cloned.tracking_id(), Some("ROUTER_1"));
+        assert_eq!(cloned.to_string(), throttled.to_string());
+
+        let not_found = Error::from(ApiError::new(StatusCode::NOT_FOUND));
+        assert!(matches!(not_found, Error::NotFound(_)));
+        assert!(!not_found.is_retryable());
+        assert_eq!(not_found.retry_after(), None);
+
+        let unauthorized = Error::from(ApiError {
+            message: Some("Invalid token".to_string()),
+            ..ApiError::new(StatusCode::UNAUTHORIZED)
+        });
+        assert_eq!(
+            unauthorized.to_string(),
+            "Authentication failed: HTTP 401 Unauthorized: Invalid token"
+        );
+        assert_eq!(
+            Error::Authentication(None).to_string(),
+            "Authentication failed"
+        );
+
+        assert!(Error::from(ApiError::new(StatusCode::BAD_GATEWAY)).is_retryable());
+        assert!(!Error::from(ApiError::new(StatusCode::BAD_REQUEST)).is_retryable());
+        assert!(Error::Closed("gone".to_string()).is_retryable());
+        assert!(!Error::Other("bug".to_string()).is_retryable());
+
+        let ws = |error| Error::Tungstenite(Arc::new(error), "ws".to_string());
+        assert!(ws(WsError::ConnectionClosed).is_retryable());
+        assert!(ws(WsError::Io(std::io::ErrorKind::ConnectionReset.into())).is_retryable());
+        assert!(!ws(WsError::Io(std::io::ErrorKind::PermissionDenied.into())).is_retryable());
+        assert!(!ws(WsError::Url(
+            tokio_tungstenite::tungstenite::error::UrlError::NoHostName
+        ))
+        .is_retryable());
+        let response = |status| {
+            let mut response = tokio_tungstenite::tungstenite::http::Response::new(None);
+            *response.status_mut() = status;
+            ws(WsError::Http(response))
+        };
+        assert!(response(StatusCode::SERVICE_UNAVAILABLE).is_retryable());
+        assert!(!response(StatusCode::FORBIDDEN).is_retryable());
+    }
+}
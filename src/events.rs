@@ -0,0 +1,388 @@
+#![deny(missing_docs)]
+//! High-level view of the events received from an event stream
+
+use crate::{
+    error::Error, ActivityType, AttachmentAction, Event, GlobalId, GlobalIdType, Message,
+    MessageActivity, SpaceActivity, Webex,
+};
+use futures::{Stream, StreamExt};
+
+/// An [`Event`] classified by what happened, with the IDs needed to act on it already resolved to
+/// [`GlobalId`]s.
+///
+/// Created from an [`Event`] with `WebexEvent::from(&event)`, or received directly from
+/// [`Webex::typed_event_stream()`]. The `message` and `action` payloads are only filled in by
+/// [`Webex::enrich_event()`] (or an enriching stream).
+#[derive(Clone, Debug)]
+pub enum WebexEvent {
+    /// A message was posted, with or without attachments.
+    MessageCreated {
+        /// ID of the new message.
+        id: GlobalId,
+        /// Room the message was posted in.
+        room_id: Option<GlobalId>,
+        /// Author of the message.
+        person_id: Option<GlobalId>,
+        /// The message itself, once fetched.
+        message: Option<Box<Message>>,
+    },
+    /// A message was deleted.
+    MessageDeleted {
+        /// ID of the deleted message.
+        id: GlobalId,
+        /// Person who deleted the message.
+        person_id: Option<GlobalId>,
+    },
+    /// An adaptive card was submitted.
+    CardSubmitted {
+        /// ID of the [`AttachmentAction`] holding the inputs.
+        id: GlobalId,
+        /// Person who submitted the card.
+        person_id: Option<GlobalId>,
+        /// The attachment action, once fetched.
+        action: Option<Box<AttachmentAction>>,
+    },
+    /// Someone (possibly the client itself) was added to a room.
+    MembershipJoined {
+        /// Room that was joined.
+        room_id: GlobalId,
+        /// Person who performed the action.
+        person_id: Option<GlobalId>,
+        /// Person who was added, which may differ from `person_id`.
+        member_id: Option<GlobalId>,
+    },
+    /// Someone (possibly the client itself) left or was removed from a room.
+    MembershipLeft {
+        /// Room that was left.
+        room_id: GlobalId,
+        /// Person who performed the action.
+        person_id: Option<GlobalId>,
+        /// Person who left or was removed, which may differ from `person_id`.
+        member_id: Option<GlobalId>,
+    },
+    /// A room was created with the client in it.
+    RoomCreated {
+        /// The new room.
+        room_id: GlobalId,
+        /// Person who created the room.
+        person_id: Option<GlobalId>,
+    },
+    /// A room was changed (e.g. renamed, locked or unlocked).
+    RoomUpdated {
+        /// The room that changed.
+        room_id: GlobalId,
+        /// Person who changed the room.
+        person_id: Option<GlobalId>,
+    },
+    /// Someone started typing.
+    Typing {
+        /// Room the person is typing in.
+        room_id: Option<GlobalId>,
+        /// Person who is typing.
+        person_id: Option<GlobalId>,
+    },
+    /// Any other event, or an event whose IDs could not be resolved.
+    Unknown(Box<Event>),
+}
+
+impl From<&Event> for WebexEvent {
+    fn from(event: &Event) -> Self {
+        let activity_type = event.activity_type();
+        let person_id = person_id(event);
+        if activity_type == ActivityType::StartTyping {
+            return Self::Typing {
+                room_id: conversation_id(event),
+                person_id,
+            };
+        }
+        let id = match &activity_type {
+            ActivityType::Message(
+                MessageActivity::Posted | MessageActivity::Shared | MessageActivity::Deleted,
+            )
+            | ActivityType::AdaptiveCardSubmit
+            | ActivityType::Space(
+                SpaceActivity::Joined
+                | SpaceActivity::Left
+                | SpaceActivity::Created
+                | SpaceActivity::Changed,
+            ) => match event.try_global_id() {
+                Ok(id) => id,
+                Err(e) => {
+                    log::warn!("Could not get ID of {activity_type:?} event: {e}");
+                    return Self::Unknown(Box::new(event.clone()));
+                }
+            },
+            _ => return Self::Unknown(Box::new(event.clone())),
+        };
+        match activity_type {
+            ActivityType::Message(MessageActivity::Deleted) => {
+                Self::MessageDeleted { id, person_id }
+            }
+            ActivityType::Message(_) => Self::MessageCreated {
+                id,
+                room_id: target_room_id(event).or_else(|| conversation_id(event)),
+                person_id,
+                message: None,
+            },
+            ActivityType::AdaptiveCardSubmit => Self::CardSubmitted {
+                id,
+                person_id,
+                action: None,
+            },
+            ActivityType::Space(SpaceActivity::Joined) => Self::MembershipJoined {
+                room_id: id,
+                person_id,
+                member_id: member_id(event),
+            },
+            ActivityType::Space(SpaceActivity::Left) => Self::MembershipLeft {
+                room_id: id,
+                person_id,
+                member_id: member_id(event),
+            },
+            ActivityType::Space(SpaceActivity::Created) => Self::RoomCreated {
+                room_id: id,
+                person_id,
+            },
+            _ => Self::RoomUpdated {
+                room_id: id,
+                person_id,
+            },
+        }
+    }
+}
+
+fn person_id(event: &Event) -> Option<GlobalId> {
+    event
+        .data
+        .activity
+        .as_ref()
+        .map(|activity| &activity.actor)
+        .or(event.data.actor.as_ref())
+        .filter(|actor| !actor.id.is_empty())
+        .map(|actor| {
+            GlobalId::new_with_cluster_unchecked(GlobalIdType::Person, actor.id.clone(), None)
+        })
+}
+
+/// Person added to or removed from a room, which is the object of the activity.
+fn member_id(event: &Event) -> Option<GlobalId> {
+    let object = &event.data.activity.as_ref()?.object;
+    let id = object.id.as_ref().filter(|id| !id.is_empty())?;
+    (object.object_type == "person")
+        .then(|| GlobalId::new_with_cluster_unchecked(GlobalIdType::Person, id.clone(), None))
+}
+
+/// Room of a message activity, which is its target.
+fn target_room_id(event: &Event) -> Option<GlobalId> {
+    let target = event.data.activity.as_ref()?.target.as_ref()?;
+    (target.object_type == "conversation").then(|| {
+        GlobalId::new_with_cluster_unchecked(
+            GlobalIdType::Room,
+            target
+                .global_id
+                .clone()
+                .unwrap_or_else(|| target.id.clone()),
+            None,
+        )
+    })
+}
+
+fn conversation_id(event: &Event) -> Option<GlobalId> {
+    event
+        .data
+        .conversation_id
+        .clone()
+        .map(|id| GlobalId::new_with_cluster_unchecked(GlobalIdType::Room, id, None))
+}
+
+impl Webex {
+    /// Fetch the payload of a [`WebexEvent::MessageCreated`] or [`WebexEvent::CardSubmitted`]
+    /// event, if it hasn't been fetched already. Other events are left untouched.
+    pub async fn enrich_event(&self, event: &mut WebexEvent) -> Result<(), Error> {
+        match event {
+            WebexEvent::MessageCreated {
+                id, message: None, ..
+            } => {
+                let fetched: Message = self.get(id).await?;
+                if let WebexEvent::MessageCreated { message, .. } = event {
+                    *message = Some(Box::new(fetched));
+                }
+            }
+            WebexEvent::CardSubmitted {
+                id, action: None, ..
+            } => {
+                let fetched: AttachmentAction = self.get(id).await?;
+                if let WebexEvent::CardSubmitted { action, .. } = event {
+                    *action = Some(Box::new(fetched));
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Get an event stream handle that yields [`WebexEvent`]s.
+    ///
+    /// With `enrich`, the message or attachment action of each new message or card submission
+    /// is fetched before the event is returned (see [`enrich_event()`](Self::enrich_event)), and
+    /// a failed fetch is returned as an error in place of the event.
+    pub async fn typed_event_stream(
+        &self,
+        enrich: bool,
+    ) -> Result<impl Stream<Item = Result<WebexEvent, Error>> + Unpin, Error> {
+        let events = self.event_stream().await?;
+        let webex = self.clone();
+        Ok(Box::pin(events.then(move |event| {
+            let webex = webex.clone();
+            async move {
+                let mut event = WebexEvent::from(&event?);
+                if enrich {
+                    webex.enrich_event(&mut event).await?;
+                }
+                Ok(event)
+            }
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::mock_server, Activity, Actor, EventData, Object, Target};
+
+    const UUID: &str = "1ab849e0-9ab4-11ee-a70f-d9b57e49f8bf";
+
+    fn event(event_type: &str, verb: &str, target: Option<Target>) -> Event {
+        Event {
+            data: EventData {
+                event_type: event_type.to_string(),
+                activity: Some(Activity {
+                    verb: verb.to_string(),
+                    id: UUID.to_string(),
+                    actor: Actor {
+                        id: UUID.to_string(),
+                        ..Actor::default()
+                    },
+                    target,
+                    ..Activity::default()
+                }),
+                ..EventData::default()
+            },
+            ..Event::default()
+        }
+    }
+
+    fn target(object_type: &str) -> Target {
+        Target {
+            id: UUID.to_string(),
+            object_type: object_type.to_string(),
+            url: String::new(),
+            participants: None,
+            activities: None,
+            tags: Vec::new(),
+            global_id: Some(UUID.to_string()),
+        }
+    }
+
+    #[test]
+    fn message_created() {
+        let event = event(
+            "conversation.activity",
+            "post",
+            Some(target("conversation")),
+        );
+        let WebexEvent::MessageCreated {
+            id,
+            room_id,
+            person_id,
+            message,
+        } = WebexEvent::from(&event)
+        else {
+            panic!("expected MessageCreated");
+        };
+        assert_eq!(id, event.try_global_id().unwrap());
+        assert!(room_id.unwrap().check_type(GlobalIdType::Room).is_ok());
+        assert!(person_id.unwrap().check_type(GlobalIdType::Person).is_ok());
+        assert!(message.is_none());
+    }
+
+    #[test]
+    fn membership_and_cards() {
+        let mut event_with_target =
+            event("conversation.activity", "add", Some(target("conversation")));
+        assert!(matches!(
+            WebexEvent::from(&event_with_target),
+            WebexEvent::MembershipJoined {
+                member_id: None,
+                ..
+            }
+        ));
+        let member = "2bc8c9e1-9ab4-11ee-a70f-d9b57e49f8bf";
+        if let Some(activity) = event_with_target.data.activity.as_mut() {
+            activity.object = Object {
+                id: Some(member.to_string()),
+                object_type: "person".to_string(),
+                ..Object::default()
+            };
+        }
+        let WebexEvent::MembershipJoined {
+            person_id,
+            member_id,
+            ..
+        } = WebexEvent::from(&event_with_target)
+        else {
+            panic!("expected MembershipJoined");
+        };
+        let person = |id: &str| {
+            GlobalId::new_with_cluster_unchecked(GlobalIdType::Person, id.to_string(), None)
+        };
+        assert_eq!(person_id, Some(person(UUID)));
+        assert_eq!(member_id, Some(person(member)));
+        assert!(matches!(
+            WebexEvent::from(&event("conversation.activity", "cardAction", None)),
+            WebexEvent::CardSubmitted { action: None, .. }
+        ));
+        // Joined events need a target to find the room
+        assert!(matches!(
+            WebexEvent::from(&event("conversation.activity", "add", None)),
+            WebexEvent::Unknown(_)
+        ));
+        assert!(matches!(
+            WebexEvent::from(&event("conversation.activity", "acknowledge", None)),
+            WebexEvent::Unknown(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn enrich_fetches_message_once() {
+        let (prefix, requests) =
+            mock_server(vec![("200 OK", "", r#"{"id":"abc","text":"hello"}"#)]).await;
+        let webex = Webex::builder("token")
+            .rest_url(prefix)
+            .wdm_url("http://localhost")
+            .build()
+            .await
+            .unwrap();
+        let mut typed = WebexEvent::from(&event(
+            "conversation.activity",
+            "post",
+            Some(target("conversation")),
+        ));
+        webex.enrich_event(&mut typed).await.unwrap();
+        // Already enriched, so no second request
+        webex.enrich_event(&mut typed).await.unwrap();
+        let WebexEvent::MessageCreated {
+            id,
+            message: Some(message),
+            ..
+        } = typed
+        else {
+            panic!("expected an enriched MessageCreated");
+        };
+        assert_eq!(message.text.as_deref(), Some("hello"));
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [format!("GET /messages/{} HTTP/1.1", id.id())]
+        );
+    }
+}
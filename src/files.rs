@@ -0,0 +1,429 @@
+#![deny(missing_docs)]
+//! Uploading and downloading message file attachments
+
+use crate::{error::Error, AuthorizationType, Message, MessageOut, Webex};
+use reqwest::{
+    header::{HeaderMap, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE},
+    multipart::{Form, Part},
+    Method,
+};
+use std::{
+    path::{Path, PathBuf},
+    pin::Pin,
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A file to upload with [`Webex::send_message_with_file()`].
+///
+/// The MIME type is guessed from the file name's extension unless set with
+/// [`mime_type()`](Self::mime_type), falling back to `application/octet-stream`.
+#[must_use]
+pub struct FileUpload {
+    filename: String,
+    mime_type: Option<String>,
+    source: Source,
+}
+
+enum Source {
+    Bytes(Vec<u8>),
+    Path(PathBuf),
+    Reader(Pin<Box<dyn AsyncRead + Send>>),
+}
+
+impl FileUpload {
+    /// Upload the given contents, e.g. a report generated in memory.
+    pub fn from_bytes(filename: impl Into<String>, bytes: impl Into<Vec<u8>>) -> Self {
+        Self {
+            filename: filename.into(),
+            mime_type: None,
+            source: Source::Bytes(bytes.into()),
+        }
+    }
+
+    /// Upload a local file, named after the last component of `path`. The file is only opened
+    /// when the message is sent, and streamed rather than read into memory.
+    pub fn from_path(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        Self {
+            filename: path.file_name().map_or_else(
+                || "file".to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            ),
+            mime_type: None,
+            source: Source::Path(path),
+        }
+    }
+
+    /// Upload everything read from `reader`, streaming it as it is read.
+    pub fn from_reader(
+        filename: impl Into<String>,
+        reader: impl AsyncRead + Send + 'static,
+    ) -> Self {
+        Self {
+            filename: filename.into(),
+            mime_type: None,
+            source: Source::Reader(Box::pin(reader)),
+        }
+    }
+
+    /// Name of the file as shown in the room.
+    pub fn filename(mut self, filename: impl Into<String>) -> Self {
+        self.filename = filename.into();
+        self
+    }
+
+    /// MIME type of the file, instead of guessing it from the file name.
+    pub fn mime_type(mut self, mime_type: impl Into<String>) -> Self {
+        self.mime_type = Some(mime_type.into());
+        self
+    }
+
+    fn resolved_mime_type(&self) -> String {
+        self.mime_type.clone().unwrap_or_else(|| {
+            mime_guess::from_path(Path::new(&self.filename))
+                .first_or_octet_stream()
+                .to_string()
+        })
+    }
+
+    async fn into_part(self) -> Result<Part, Error> {
+        let mime_type = self.resolved_mime_type();
+        let part = match self.source {
+            Source::Bytes(bytes) => Part::bytes(bytes),
+            Source::Path(path) => {
+                let file = tokio::fs::File::open(&path).await?;
+                let length = file.metadata().await?.len();
+                Part::stream_with_length(reader_body(Box::pin(file)), length)
+            }
+            Source::Reader(reader) => Part::stream(reader_body(reader)),
+        };
+        Ok(part.file_name(self.filename).mime_str(&mime_type)?)
+    }
+}
+
+fn reader_body(reader: Pin<Box<dyn AsyncRead + Send>>) -> reqwest::Body {
+    reqwest::Body::wrap_stream(futures::stream::try_unfold(
+        reader,
+        |mut reader| async move {
+            let mut chunk = vec![0; CHUNK_SIZE];
+            let read = reader.read(&mut chunk).await?;
+            if read == 0 {
+                return Ok::<_, std::io::Error>(None);
+            }
+            chunk.truncate(read);
+            Ok(Some((chunk, reader)))
+        },
+    ))
+}
+
+/// Text fields of the multipart form for `message`.
+fn message_form(message: &MessageOut) -> Result<Form, Error> {
+    let serde_json::Value::Object(fields) = serde_json::to_value(message)? else {
+        return Err("message did not serialize to an object".into());
+    };
+    let mut form = Form::new();
+    for (name, value) in fields {
+        match value {
+            serde_json::Value::String(value) => form = form.text(name, value),
+            serde_json::Value::Null => {}
+            _ => {
+                return Err(format!(
+                    "`{name}` cannot be sent along with an uploaded file (one file per message)"
+                )
+                .into())
+            }
+        }
+    }
+    Ok(form)
+}
+
+/// Metadata of a file attached to a message, taken from the response headers.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FileInfo {
+    /// File name, from the `Content-Disposition` header.
+    pub filename: Option<String>,
+    /// MIME type, from the `Content-Type` header.
+    pub content_type: Option<String>,
+    /// Size in bytes, from the `Content-Length` header.
+    pub size: Option<u64>,
+}
+
+impl FileInfo {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+        Self {
+            filename: header(CONTENT_DISPOSITION).and_then(disposition_filename),
+            content_type: header(CONTENT_TYPE).map(str::to_string),
+            size: header(CONTENT_LENGTH).and_then(|length| length.parse().ok()),
+        }
+    }
+}
+
+/// File name from a `Content-Disposition` header, preferring the RFC 5987 `filename*` form.
+fn disposition_filename(disposition: &str) -> Option<String> {
+    let params = disposition.split(';').skip(1).filter_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        Some((name.trim().to_ascii_lowercase(), value.trim()))
+    });
+    let mut filename = None;
+    for (name, value) in params {
+        match name.as_str() {
+            "filename*" => {
+                // charset'language'percent-encoded-value
+                if let Some(encoded) = value.splitn(3, '\'').nth(2) {
+                    return Some(percent_decode(encoded));
+                }
+            }
+            "filename" => filename = Some(value.trim_matches('"').to_string()),
+            _ => {}
+        }
+    }
+    filename
+}
+
+fn percent_decode(encoded: &str) -> String {
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut rest = encoded.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        if let Some(decoded) = decoded {
+            bytes.push(decoded);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+impl Webex {
+    /// Get the metadata of a file attached to a message (one of [`Message::files`]) without
+    /// downloading it.
+    ///
+    /// # Errors
+    /// * [`Error::Throttled`] - returned on HTTP 423/429, e.g. while the file is still being
+    ///   scanned for malware, once the [`RetryPolicy`](crate::retry::RetryPolicy) gives up.
+    /// * [`Error::NotFound`] - if there is no file at `url`, or it isn't visible to the caller.
+    /// * [`Error::Authentication`] - if the token is invalid or expired.
+    /// * [`Error::Api`] - returned on any other non-2xx response.
+    /// * [`Error::UntrustedUrl`] - if `url` is neither on a Webex host nor on the configured REST
+    ///   URL, so the token would leak.
+    pub async fn file_info(&self, url: &str) -> Result<FileInfo, Error> {
+        let res = self
+            .client
+            .api_raw(Method::HEAD, url, AuthorizationType::Bearer(&self.token))
+            .await?;
+        Ok(FileInfo::from_headers(res.headers()))
+    }
+
+    /// Download a file attached to a message (one of [`Message::files`]) into memory.
+    ///
+    /// # Errors
+    /// * [`Error::Throttled`] - returned on HTTP 423/429, e.g. while the file is still being
+    ///   scanned for malware, once the [`RetryPolicy`](crate::retry::RetryPolicy) gives up.
+    /// * [`Error::NotFound`] - if there is no file at `url`, or it isn't visible to the caller.
+    /// * [`Error::Authentication`] - if the token is invalid or expired.
+    /// * [`Error::Api`] - returned on any other non-2xx response.
+    /// * [`Error::UntrustedUrl`] - if `url` is neither on a Webex host nor on the configured REST
+    ///   URL, so the token would leak.
+    /// * [`Error::Reqwest`] - if the download is interrupted.
+    pub async fn download_file(&self, url: &str) -> Result<(FileInfo, Vec<u8>), Error> {
+        let res = self
+            .client
+            .api_raw(Method::GET, url, AuthorizationType::Bearer(&self.token))
+            .await?;
+        let info = FileInfo::from_headers(res.headers());
+        Ok((info, res.bytes().await?.to_vec()))
+    }
+
+    /// Download a file attached to a message (one of [`Message::files`]), streaming it into
+    /// `writer` as it is received. The writer is flushed, but not shut down.
+    ///
+    /// # Errors
+    /// As for [`download_file()`](Self::download_file), plus [`Error::Io`] if writing fails.
+    pub async fn download_file_to<W: AsyncWrite + Unpin>(
+        &self,
+        url: &str,
+        writer: &mut W,
+    ) -> Result<FileInfo, Error> {
+        let mut res = self
+            .client
+            .api_raw(Method::GET, url, AuthorizationType::Bearer(&self.token))
+            .await?;
+        let info = FileInfo::from_headers(res.headers());
+        while let Some(chunk) = res.chunk().await? {
+            writer.write_all(&chunk).await?;
+        }
+        writer.flush().await?;
+        Ok(info)
+    }
+
+    /// Send a message with a local file attached, uploaded as `multipart/form-data`.
+    ///
+    /// Webex allows one file per message, so `message` must not have `files` (or card
+    /// `attachments`) set. Uploads are not retried when throttled, since the file may be a stream
+    /// that can't be read again.
+    ///
+    /// # Errors
+    /// * [`Error::Other`] - if `message` has `files` or `attachments` set.
+    /// * [`Error::Io`] - if the file cannot be read.
+    /// * [`Error::Throttled`] - returned on HTTP 423/429 with an optional Retry-After.
+    /// * [`Error::NotFound`] - if the room or person to send to doesn't exist.
+    /// * [`Error::Authentication`] - if the token is invalid or expired.
+    /// * [`Error::Api`] - returned on any other non-2xx response.
+    /// * [`Error::Json`] - returned when the return value cannot be deserialised. (If this
+    ///   happens, this is a library bug and should be reported.)
+    pub async fn send_message_with_file(
+        &self,
+        message: &MessageOut,
+        file: FileUpload,
+    ) -> Result<Message, Error> {
+        let form = message_form(message)?.part("files", file.into_part().await?);
+        self.client
+            .api_post_multipart("messages", form, AuthorizationType::Bearer(&self.token))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{mock_server, mock_server_with_bodies};
+
+    #[test]
+    fn mime_type_detection() {
+        assert_eq!(
+            FileUpload::from_path("/tmp/report.csv").resolved_mime_type(),
+            "text/csv"
+        );
+        assert_eq!(
+            FileUpload::from_bytes("chart.png", Vec::new()).resolved_mime_type(),
+            "image/png"
+        );
+        assert_eq!(
+            FileUpload::from_bytes("log", Vec::new()).resolved_mime_type(),
+            "application/octet-stream"
+        );
+        assert_eq!(
+            FileUpload::from_reader("log", tokio::io::empty())
+                .mime_type("text/plain")
+                .resolved_mime_type(),
+            "text/plain"
+        );
+        assert_eq!(
+            FileUpload::from_path("/tmp/report.csv").filename,
+            "report.csv"
+        );
+    }
+
+    #[test]
+    fn one_file_per_message() {
+        let message = MessageOut {
+            room_id: Some("r".to_string()),
+            files: Some(vec!["https://example.com/a.png".to_string()]),
+            ..MessageOut::default()
+        };
+        assert!(message_form(&message).is_err());
+    }
+
+    #[test]
+    fn content_disposition() {
+        assert_eq!(
+            disposition_filename(r#"attachment; filename="report.csv""#).as_deref(),
+            Some("report.csv")
+        );
+        assert_eq!(
+            disposition_filename(
+                r#"attachment; filename="r?sum?.pdf"; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf"#
+            )
+            .as_deref(),
+            Some("résumé.pdf")
+        );
+        assert_eq!(
+            disposition_filename(r#"attachment; filename*=garbage; filename="plain.txt""#)
+                .as_deref(),
+            Some("plain.txt")
+        );
+        assert_eq!(disposition_filename("inline"), None);
+    }
+
+    #[tokio::test]
+    async fn downloads_files() {
+        let headers = "content-disposition: attachment; filename=\"log.txt\"\r\n";
+        let (prefix, requests) = mock_server(vec![
+            ("200 OK", headers, ""),
+            ("200 OK", headers, "line 1\nline 2\n"),
+            ("200 OK", headers, "line 1\nline 2\n"),
+        ])
+        .await;
+        let webex = Webex::builder("token")
+            .rest_url(format!("{prefix}/v1"))
+            .wdm_url("http://localhost")
+            .build()
+            .await
+            .unwrap();
+        let err = webex
+            .download_file("https://files.example.com/v1/contents/abc")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::UntrustedUrl(_)), "{err:?}");
+        let url = format!("{prefix}/v1/contents/abc");
+        let info = webex.file_info(&url).await.unwrap();
+        assert_eq!(info.filename.as_deref(), Some("log.txt"));
+        let (info, bytes) = webex.download_file(&url).await.unwrap();
+        assert_eq!(info.size, Some(14));
+        assert_eq!(bytes, b"line 1\nline 2\n");
+        let mut written = Vec::new();
+        webex.download_file_to(&url, &mut written).await.unwrap();
+        assert_eq!(written, bytes);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                "HEAD /v1/contents/abc HTTP/1.1",
+                "GET /v1/contents/abc HTTP/1.1",
+                "GET /v1/contents/abc HTTP/1.1",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn uploads_bytes() {
+        let (prefix, requests, bodies) =
+            mock_server_with_bodies(vec![("200 OK", "", r#"{"id":"m"}"#)]).await;
+        let webex = Webex::builder("token")
+            .rest_url(prefix)
+            .wdm_url("http://localhost")
+            .build()
+            .await
+            .unwrap();
+        let message = MessageOut {
+            room_id: Some("r".to_string()),
+            text: Some("Weekly report".to_string()),
+            ..MessageOut::default()
+        };
+        let sent = webex
+            .send_message_with_file(&message, FileUpload::from_bytes("report.csv", "a,b\n1,2\n"))
+            .await
+            .unwrap();
+        assert_eq!(sent.id.as_deref(), Some("m"));
+        assert_eq!(*requests.lock().unwrap(), ["POST /messages HTTP/1.1"]);
+        let body = bodies.lock().unwrap()[0].clone();
+        assert!(body.contains("name=\"roomId\"\r\n\r\nr\r\n"), "{body}");
+        assert!(
+            body.contains("name=\"text\"\r\n\r\nWeekly report\r\n"),
+            "{body}"
+        );
+        assert!(
+            body.contains(
+                "name=\"files\"; filename=\"report.csv\"\r\nContent-Type: text/csv\r\n\r\na,b\n1,2\n\r\n"
+            ),
+            "{body}"
+        );
+    }
+}
@@ -0,0 +1,337 @@
+#![deny(missing_docs)]
+//! Walking back through the message history of a room, and exporting it
+
+use crate::{error::Error, GlobalId, Message, MessageListParams, Webex};
+use chrono::{DateTime, Utc};
+use futures::{future, stream, Stream, StreamExt, TryStreamExt};
+use std::fmt::Write as _;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// Format of an exported transcript, see [`RoomHistory::export()`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExportFormat {
+    /// One JSON [`Message`] per line.
+    Jsonl,
+    /// Markdown, with thread replies as block quotes under their parent.
+    Markdown,
+    /// A standalone HTML page.
+    Html,
+}
+
+/// Message history of a room, newest first. Created by [`Webex::history()`].
+///
+/// ```no_run
+/// # async fn example(webex: webex::Webex, room_id: webex::GlobalId) -> Result<(), webex::error::Error> {
+/// use futures::TryStreamExt;
+///
+/// let since = chrono::Utc::now() - chrono::Duration::days(7);
+/// let history = webex.history(&room_id).since(since).include_replies(true);
+/// let last_week: Vec<_> = history.stream().try_collect().await?;
+///
+/// let mut file = tokio::fs::File::create("transcript.md").await?;
+/// history.export(webex::ExportFormat::Markdown, &mut file).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[must_use]
+#[derive(Clone)]
+pub struct RoomHistory<'a> {
+    webex: &'a Webex,
+    room_id: String,
+    before: Option<DateTime<Utc>>,
+    since: Option<DateTime<Utc>>,
+    limit: Option<usize>,
+    include_replies: bool,
+    mentioned_me: bool,
+}
+
+impl Webex {
+    /// Message history of a room, see [`RoomHistory`].
+    pub fn history(&self, room_id: &GlobalId) -> RoomHistory<'_> {
+        RoomHistory {
+            webex: self,
+            room_id: room_id.id().to_string(),
+            before: None,
+            since: None,
+            limit: None,
+            include_replies: false,
+            mentioned_me: false,
+        }
+    }
+}
+
+impl RoomHistory<'_> {
+    /// Start from messages sent before this time, instead of the latest message.
+    pub const fn before(mut self, before: DateTime<Utc>) -> Self {
+        self.before = Some(before);
+        self
+    }
+
+    /// Stop at the first top-level message sent before this time.
+    pub const fn since(mut self, since: DateTime<Utc>) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// Stop after this many messages (including replies).
+    pub const fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Also fetch the replies of each top-level message. Replies come right before their parent
+    /// (they are newer), newest first. Without this, replies are skipped.
+    pub const fn include_replies(mut self, include_replies: bool) -> Self {
+        self.include_replies = include_replies;
+        self
+    }
+
+    /// Only list messages mentioning the caller. Bots can only list messages of group rooms
+    /// this way.
+    pub const fn mentioned_me(mut self, mentioned_me: bool) -> Self {
+        self.mentioned_me = mentioned_me;
+        self
+    }
+
+    fn params(&self) -> MessageListParams<'_> {
+        MessageListParams {
+            mentioned_people: if self.mentioned_me { &["me"] } else { &[] },
+            before: self.before,
+            ..MessageListParams::new(&self.room_id)
+        }
+    }
+
+    /// Stream the messages, newest first, fetching pages as they are consumed.
+    pub fn stream(&self) -> impl Stream<Item = Result<Message, Error>> + '_ {
+        let since = self.since;
+        self.webex
+            .list_stream_with_params::<Message>(self.params())
+            .try_filter(|message| future::ready(message.parent_id.is_none()))
+            .try_take_while(move |message| {
+                future::ready(Ok(since.is_none_or(|since| {
+                    message.created.is_none_or(|created| created >= since)
+                })))
+            })
+            .map_ok(move |message| {
+                let replies = match (&message.id, self.include_replies) {
+                    (Some(id), true) => self
+                        .webex
+                        .list_stream_with_params::<Message>(MessageListParams {
+                            parent_id: Some(id),
+                            ..MessageListParams::new(&self.room_id)
+                        })
+                        .left_stream(),
+                    _ => stream::empty().right_stream(),
+                };
+                replies.chain(stream::once(future::ok(message)))
+            })
+            .try_flatten()
+            .take(self.limit.unwrap_or(usize::MAX))
+    }
+
+    /// Write a transcript of the messages to `writer`, oldest first, with replies following
+    /// their parent. The whole history is fetched before anything is written. Returns the number
+    /// of messages written.
+    ///
+    /// # Errors
+    /// Errors from listing messages or writing to `writer`.
+    pub async fn export<W: AsyncWrite + Unpin>(
+        &self,
+        format: ExportFormat,
+        writer: &mut W,
+    ) -> Result<usize, Error> {
+        let mut messages: Vec<Message> = self.stream().try_collect().await?;
+        // Newest first, with replies before their parent, so this puts every thread in order
+        messages.reverse();
+        let transcript = match format {
+            ExportFormat::Jsonl => {
+                messages
+                    .iter()
+                    .try_fold(String::new(), |mut out, message| {
+                        out.push_str(&serde_json::to_string(message)?);
+                        out.push('\n');
+                        Ok::<_, Error>(out)
+                    })?
+            }
+            ExportFormat::Markdown => messages.iter().fold(String::new(), |mut out, message| {
+                let quote = if message.parent_id.is_some() {
+                    "> "
+                } else {
+                    ""
+                };
+                let _ = writeln!(
+                    out,
+                    "{quote}**{}** {}  ",
+                    author(message),
+                    timestamp(message)
+                );
+                for line in body(message).lines() {
+                    let _ = writeln!(out, "{quote}{line}");
+                }
+                out.push('\n');
+                out
+            }),
+            ExportFormat::Html => {
+                let mut out = "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Room history</title></head>\n<body>\n".to_string();
+                for message in &messages {
+                    let class = if message.parent_id.is_some() {
+                        "message reply"
+                    } else {
+                        "message"
+                    };
+                    let _ = writeln!(
+                        out,
+                        "<div class=\"{class}\"><b>{}</b> <time>{}</time><p>{}</p></div>",
+                        escape_html(author(message)),
+                        escape_html(&timestamp(message)),
+                        escape_html(body(message)).replace('\n', "<br>")
+                    );
+                }
+                out.push_str("</body>\n</html>\n");
+                out
+            }
+        };
+        writer.write_all(transcript.as_bytes()).await?;
+        writer.flush().await?;
+        Ok(messages.len())
+    }
+}
+
+fn timestamp(message: &Message) -> String {
+    message.created.map_or_else(String::new, |created| {
+        created.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+    })
+}
+
+fn author(message: &Message) -> &str {
+    message
+        .person_email
+        .as_deref()
+        .or(message.person_id.as_deref())
+        .unwrap_or("unknown")
+}
+
+fn body(message: &Message) -> &str {
+    message
+        .markdown
+        .as_deref()
+        .or(message.text.as_deref())
+        .unwrap_or_default()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::mock_server, GlobalIdType};
+    use chrono::TimeZone;
+
+    fn message(id: &str, parent: Option<&str>, created: &str) -> String {
+        let parent = parent.map_or_else(String::new, |parent| format!(r#""parentId":"{parent}","#));
+        format!(
+            r#"{{"id":"{id}",{parent}"personEmail":"a@example.com","text":"<{id}>","created":"{created}"}}"#
+        )
+    }
+
+    #[tokio::test]
+    async fn pages_back_with_replies() {
+        let room = format!(
+            r#"{{"items":[{},{},{}]}}"#,
+            message("r1", Some("p2"), "2024-01-03T00:00:00.000Z"),
+            message("p2", None, "2024-01-02T00:00:00.000Z"),
+            message("p1", None, "2024-01-01T00:00:00.000Z"),
+        );
+        let replies = format!(
+            r#"{{"items":[{}]}}"#,
+            message("r1", Some("p2"), "2024-01-03T00:00:00.000Z")
+        );
+        let (prefix, requests) =
+            mock_server(vec![("200 OK", "", room), ("200 OK", "", replies)]).await;
+        let webex = Webex::builder("token")
+            .rest_url(prefix)
+            .wdm_url("http://localhost")
+            .build()
+            .await
+            .unwrap();
+        let room_id = GlobalId::new_with_cluster_unchecked(GlobalIdType::Room, "room".into(), None);
+        let history = webex
+            .history(&room_id)
+            .since(Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap())
+            .include_replies(true);
+
+        let mut transcript = Vec::new();
+        let count = history
+            .export(ExportFormat::Markdown, &mut transcript)
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(
+            String::from_utf8(transcript).unwrap(),
+            "**a@example.com** 2024-01-02 00:00:00 UTC  \n<p2>\n\n\
+             > **a@example.com** 2024-01-03 00:00:00 UTC  \n> <r1>\n\n"
+        );
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                "GET /messages?roomId=room HTTP/1.1",
+                "GET /messages?roomId=room&parentId=p2 HTTP/1.1",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn replies_ignore_top_level_filters() {
+        let room = format!(
+            r#"{{"items":[{}]}}"#,
+            message("p1", None, "2024-01-02T00:00:00.000Z"),
+        );
+        let replies = format!(
+            r#"{{"items":[{}]}}"#,
+            message("r1", Some("p1"), "2024-01-04T00:00:00.000Z")
+        );
+        let (prefix, requests) =
+            mock_server(vec![("200 OK", "", room), ("200 OK", "", replies)]).await;
+        let webex = Webex::builder("token")
+            .rest_url(prefix)
+            .wdm_url("http://localhost")
+            .build()
+            .await
+            .unwrap();
+        let room_id = GlobalId::new_with_cluster_unchecked(GlobalIdType::Room, "room".into(), None);
+        let messages: Vec<Message> = webex
+            .history(&room_id)
+            .before(Utc.with_ymd_and_hms(2024, 1, 3, 0, 0, 0).unwrap())
+            .mentioned_me(true)
+            .include_replies(true)
+            .stream()
+            .try_collect()
+            .await
+            .unwrap();
+        let ids: Vec<_> = messages.iter().map(|m| m.id.as_deref().unwrap()).collect();
+        assert_eq!(ids, ["r1", "p1"]);
+        let requests = requests.lock().unwrap().clone();
+        assert!(
+            requests[0]
+                .starts_with("GET /messages?roomId=room&mentionedPeople=me&before=2024-01-03"),
+            "{requests:?}"
+        );
+        assert_eq!(
+            requests[1],
+            "GET /messages?roomId=room&parentId=p1 HTTP/1.1"
+        );
+    }
+
+    #[test]
+    fn html_is_escaped() {
+        assert_eq!(
+            escape_html("<b>\"&\"</b>"),
+            "&lt;b&gt;&quot;&amp;&quot;&lt;/b&gt;"
+        );
+    }
+}
@@ -0,0 +1,158 @@
+#![deny(missing_docs)]
+//! Keepalive pings and connection health for [`WebexEventStream`](crate::WebexEventStream)
+
+use crate::error::Error;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{Instant, Interval, MissedTickBehavior, Sleep};
+
+/// Controls how an event stream checks that its websocket connection is still alive.
+///
+/// The stream pings the server every [`ping_interval`](Self::ping_interval) and declares the
+/// connection dead once [`max_missed_pongs`](Self::max_missed_pongs) pings in a row went
+/// unanswered. Independently, it is declared dead if nothing at all (events, pings or pongs) was
+/// received for [`idle_timeout`](Self::idle_timeout).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct KeepaliveSettings {
+    /// Close the connection if nothing is received for this long.
+    pub idle_timeout: Duration,
+    /// How often to ping the server. `None` disables pings.
+    pub ping_interval: Option<Duration>,
+    /// Number of consecutive unanswered pings after which the connection is considered dead.
+    pub max_missed_pongs: u32,
+}
+
+impl Default for KeepaliveSettings {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(60),
+            ping_interval: Some(Duration::from_secs(15)),
+            max_missed_pongs: 2,
+        }
+    }
+}
+
+/// Snapshot of the health of an event stream's connection, e.g. for metrics.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct StreamHealth {
+    /// Time since anything was last received on the connection.
+    pub idle_for: Duration,
+    /// Round-trip time of the last answered ping.
+    pub last_pong_latency: Option<Duration>,
+    /// Number of pings in a row that are still unanswered.
+    pub missed_pongs: u32,
+    /// Total pings sent on this connection.
+    pub pings_sent: u64,
+    /// Total pongs received on this connection.
+    pub pongs_received: u64,
+}
+
+/// Keepalive state of a single connection.
+pub struct Keepalive {
+    settings: KeepaliveSettings,
+    idle: Pin<Box<Sleep>>,
+    ping_timer: Option<Interval>,
+    last_activity: Instant,
+    /// ID and send time of the latest ping that hasn't been answered.
+    outstanding: Option<(u64, Instant)>,
+    next_ping_id: u64,
+    last_pong_latency: Option<Duration>,
+    missed_pongs: u32,
+    pings_sent: u64,
+    pongs_received: u64,
+}
+
+impl Keepalive {
+    pub fn new(settings: KeepaliveSettings) -> Self {
+        let now = Instant::now();
+        Self {
+            settings,
+            idle: Box::pin(tokio::time::sleep_until(now + settings.idle_timeout)),
+            ping_timer: settings.ping_interval.map(|period| {
+                let mut timer = tokio::time::interval_at(now + period, period);
+                timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                timer
+            }),
+            last_activity: now,
+            outstanding: None,
+            next_ping_id: 0,
+            last_pong_latency: None,
+            missed_pongs: 0,
+            pings_sent: 0,
+            pongs_received: 0,
+        }
+    }
+
+    pub const fn settings(&self) -> KeepaliveSettings {
+        self.settings
+    }
+
+    /// Anything received on the connection shows it is alive.
+    pub fn on_message(&mut self) {
+        self.last_activity = Instant::now();
+        self.idle
+            .as_mut()
+            .reset(self.last_activity + self.settings.idle_timeout);
+    }
+
+    pub fn on_pong(&mut self, payload: &[u8]) {
+        self.pongs_received += 1;
+        // Even a late pong for an earlier ping proves the connection works
+        self.missed_pongs = 0;
+        if let Some((id, sent)) = self.outstanding {
+            if payload == id.to_be_bytes() {
+                self.last_pong_latency = Some(sent.elapsed());
+                self.outstanding = None;
+            }
+        }
+    }
+
+    /// Ready with an error once the connection should be considered dead.
+    pub fn poll_idle(&mut self, cx: &mut Context<'_>) -> Poll<Error> {
+        self.idle.as_mut().poll(cx).map(|()| {
+            Error::Timeout(format!(
+                "no activity for at least {:?}",
+                self.settings.idle_timeout
+            ))
+        })
+    }
+
+    /// Ready with the payload of a ping to send when one is due, or with an error once too many
+    /// pings went unanswered.
+    pub fn poll_ping(&mut self, cx: &mut Context<'_>) -> Poll<Result<Vec<u8>, Error>> {
+        let Some(timer) = self.ping_timer.as_mut() else {
+            return Poll::Pending;
+        };
+        if timer.poll_tick(cx).is_pending() {
+            return Poll::Pending;
+        }
+        if self.outstanding.is_some() {
+            self.missed_pongs += 1;
+            if self.missed_pongs >= self.settings.max_missed_pongs {
+                return Poll::Ready(Err(Error::Timeout(format!(
+                    "no pong received for the last {} pings",
+                    self.missed_pongs
+                ))));
+            }
+        }
+        let id = self.next_ping_id;
+        self.next_ping_id += 1;
+        self.outstanding = Some((id, Instant::now()));
+        self.pings_sent += 1;
+        Poll::Ready(Ok(id.to_be_bytes().to_vec()))
+    }
+
+    pub fn health(&self) -> StreamHealth {
+        StreamHealth {
+            idle_for: self.last_activity.elapsed(),
+            last_pong_latency: self.last_pong_latency,
+            missed_pongs: self.missed_pongs,
+            pings_sent: self.pings_sent,
+            pongs_received: self.pongs_received,
+        }
+    }
+}
@@ -19,9 +19,12 @@
 //!
 //! - Registration with Webex APIs
 //! - Monitoring an event stream
+//! - Managing and receiving webhooks
 //! - Sending direct or group messages
-//! - Getting room memberships
+//! - Listing and managing room memberships
 //! - Building `AdaptiveCards` and retrieving responses
+//! - Testing bots offline against a mock server (`webex::testing`, behind the `testing`
+//!   feature)
 //!
 //! Not all features are fully-fleshed out, particularly the `AdaptiveCard`
 //! support (only a few serializations exist, enough to create a form with a
@@ -33,27 +36,57 @@
 //! author is a current developer at Cisco, but has no direct affiliation
 //! with the Webex development team.
 
-extern crate lazy_static;
-
 pub mod adaptive_card;
 #[allow(missing_docs)]
 pub mod error;
 pub mod types;
 pub use types::*;
 pub mod auth;
-
-use error::Error;
+pub mod bot;
+mod builder;
+mod cassette;
+mod catalog;
+pub use builder::WebexBuilder;
+pub use cassette::{Cassette, CassetteMode};
+pub use catalog::{ServiceCatalog, DEFAULT_CATALOG_TTL};
+mod events;
+pub use events::WebexEvent;
+mod files;
+pub use files::{FileInfo, FileUpload};
+mod history;
+pub use history::{ExportFormat, RoomHistory};
+mod people;
+pub use people::{TokenKind, DEFAULT_PERSON_CACHE_SIZE, DEFAULT_PERSON_TTL};
+mod thread;
+pub use thread::{Thread, ThreadSummary};
+mod webhook;
+pub use webhook::{WebhookData, WebhookPayload, WebhookReceiver, SIGNATURE_HEADER};
+mod keepalive;
+pub use keepalive::{KeepaliveSettings, StreamHealth};
+mod reconnect;
+pub use reconnect::{ReconnectingEventStream, StreamEvent};
+pub mod retry;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+use cassette::StreamRecorder;
+use catalog::CatalogCache;
+use error::{ApiError, Error};
+use keepalive::Keepalive;
+use people::PersonCache;
+use retry::RetryPolicy;
 
 use crate::adaptive_card::AdaptiveCard;
-use futures::{future::try_join_all, try_join};
+use futures::{future::try_join_all, stream, try_join, Stream, TryStreamExt};
 use futures_util::{SinkExt, StreamExt};
 use log::{debug, error, trace, warn};
 use reqwest::StatusCode;
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
-    collections::{hash_map::DefaultHasher, HashMap},
-    hash::{self, Hasher},
-    sync::Mutex,
+    collections::HashMap,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
     time::Duration,
 };
 use tokio::net::TcpStream;
@@ -79,6 +112,10 @@ const U2C_HOST_PREFIX: &str = "https://u2c.wbx2.com/u2c/api/v1";
 // Default mercury URL, used when the token doesn't have permissions to list organizations.
 const DEFAULT_REGISTRATION_HOST_PREFIX: &str = "https://wdm-a.wbx2.com/wdm/api/v1";
 
+// Domains the access token may be sent to, besides the configured endpoints (e.g. for file
+// downloads).
+const WEBEX_DOMAINS: [&str; 4] = ["webex.com", "webexapis.com", "wbx2.com", "ciscospark.com"];
+
 const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 // Qualify webex devices created by this crate
@@ -92,22 +129,60 @@ pub type WStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
 #[derive(Clone)]
 #[must_use]
 pub struct Webex {
-    id: u64,
     client: RestClient,
     token: String,
+    mercury_url: Option<String>,
+    keepalive: KeepaliveSettings,
+    people: Arc<PersonCache>,
+    identity: Option<Person>,
+    catalog: Arc<CatalogCache>,
     /// Webex Device Information used for device registration
     pub device: DeviceData,
 }
 
 /// Webex Event Stream handler
+///
+/// Events can be read with [`next()`](Self::next), or through the [`Stream`] implementation to use
+/// it with [`StreamExt`] combinators and `tokio::select!`. After an error that closes the
+/// connection, the stream ends.
+///
+/// The connection is kept alive and monitored with pings, see [`KeepaliveSettings`]. Pings are
+/// only sent and answered while the stream is being polled.
 pub struct WebexEventStream {
     ws_stream: WStream,
-    timeout: Duration,
+    keepalive: Keepalive,
+    recorder: Option<StreamRecorder>,
     /// Signifies if `WebStream` is Open
     pub is_open: bool,
 }
 
 impl WebexEventStream {
+    fn new(ws_stream: WStream, keepalive: KeepaliveSettings) -> Self {
+        Self {
+            ws_stream,
+            keepalive: Keepalive::new(keepalive),
+            recorder: None,
+            is_open: true,
+        }
+    }
+
+    /// Replace the keepalive settings of this stream. Restarts the idle timeout and ping timer.
+    pub fn set_keepalive(&mut self, keepalive: KeepaliveSettings) {
+        self.keepalive = Keepalive::new(keepalive);
+    }
+
+    /// Current keepalive settings.
+    #[must_use]
+    pub const fn keepalive(&self) -> KeepaliveSettings {
+        self.keepalive.settings()
+    }
+
+    /// Health of the connection: idle time, ping latency and missed pongs.
+    #[must_use]
+    pub fn health(&self) -> StreamHealth {
+        self.keepalive.health()
+    }
+
     /// Get the next event from an event stream
     ///
     /// Returns an event or an error
@@ -117,41 +192,36 @@ impl WebexEventStream {
     /// continue to work on subsequent calls to `next()` - the errors can safely
     /// be ignored.
     pub async fn next(&mut self) -> Result<Event, Error> {
-        loop {
-            let next = self.ws_stream.next();
-
-            match tokio::time::timeout(self.timeout, next).await {
-                // Timed out
-                Err(_) => {
-                    // This does not seem to be recoverable, or at least there are conditions under
-                    // which it does not recover. Indicate that the connection is closed and a new
-                    // one will have to be opened.
-                    self.is_open = false;
-                    return Err(format!("no activity for at least {:?}", self.timeout).into());
-                }
-                // Didn't time out
-                Ok(next_result) => match next_result {
-                    None => continue,
-                    Some(msg) => match msg {
-                        Ok(msg) => {
-                            if let Some(h_msg) = self.handle_message(msg)? {
-                                return Ok(h_msg);
-                            }
-                            // `None` messages still reset the timeout (e.g. Ping to keep alive)
-                        }
-                        Err(TErr::Protocol(_) | TErr::Io(_)) => {
-                            // Protocol error probably requires a connection reset
-                            // IO error is (apart from WouldBlock) generally an error with the
-                            // underlying connection and also fatal
-                            self.is_open = false;
-                            return Err(msg.unwrap_err().to_string().into());
-                        }
-                        Err(e) => {
-                            return Err(Error::Tungstenite(e, "Error getting next_result".into()))
-                        }
-                    },
-                },
+        StreamExt::next(self)
+            .await
+            .unwrap_or_else(|| Err(Error::Closed("Web Socket Closed".to_string())))
+    }
+
+    /// Queues a ping if the websocket can take it right now; otherwise this ping is skipped, and
+    /// will count as missed.
+    fn send_ping(&mut self, cx: &mut Context<'_>, payload: Vec<u8>) -> Result<(), Error> {
+        match self.ws_stream.poll_ready_unpin(cx) {
+            Poll::Ready(Ok(())) => {
+                trace!("Sending ping");
+                self.ws_stream
+                    .start_send_unpin(TMessage::Ping(payload))
+                    .map_err(|e| self.handle_ws_error(e))
             }
+            Poll::Ready(Err(e)) => Err(self.handle_ws_error(e)),
+            Poll::Pending => Ok(()),
+        }
+    }
+
+    fn handle_ws_error(&mut self, e: TErr) -> Error {
+        match e {
+            TErr::ConnectionClosed | TErr::AlreadyClosed | TErr::Protocol(_) | TErr::Io(_) => {
+                // Protocol error probably requires a connection reset
+                // IO error is (apart from WouldBlock) generally an error with the
+                // underlying connection and also fatal
+                self.is_open = false;
+                Error::Closed(e.to_string())
+            }
+            e => Error::Tungstenite(Arc::new(e), "Error getting next_result".into()),
         }
     }
 
@@ -168,7 +238,7 @@ impl WebexEventStream {
                 }
             }
             TMessage::Text(t) => {
-                debug!("text: {}", t);
+                debug!("text: {t}");
                 Ok(None)
             }
             TMessage::Ping(_) => {
@@ -176,12 +246,13 @@ impl WebexEventStream {
                 Ok(None)
             }
             TMessage::Close(t) => {
-                debug!("close: {:?}", t);
+                debug!("close: {t:?}");
                 self.is_open = false;
                 Err(Error::Closed("Web Socket Closed".to_string()))
             }
-            TMessage::Pong(_) => {
+            TMessage::Pong(payload) => {
                 debug!("Pong!");
+                self.keepalive.on_pong(&payload);
                 Ok(None)
             }
             TMessage::Frame(_) => {
@@ -214,19 +285,84 @@ impl WebexEventStream {
                             }
                             _ => Err(format!("Received {msg:?} in reply to auth message").into()),
                         },
-                        Err(e) => Err(format!("Received error from websocket: {e}").into()),
+                        Err(e) => Err(Error::Tungstenite(
+                            Arc::new(e),
+                            "Received error from websocket".to_string(),
+                        )),
                     },
-                    None => Err("Websocket closed".to_string().into()),
+                    None => Err(Error::Closed(
+                        "Websocket closed during authentication".to_string(),
+                    )),
                 }
             }
             Err(e) => Err(Error::Tungstenite(
-                e,
+                Arc::new(e),
                 "failed to send authentication".to_string(),
             )),
         }
     }
 }
 
+impl Stream for WebexEventStream {
+    type Item = Result<Event, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if !self.is_open {
+            return Poll::Ready(None);
+        }
+        // Poll until pending, so that the ping timer wakes us up again
+        while let Poll::Ready(result) = self.keepalive.poll_ping(cx) {
+            match result.and_then(|payload| self.send_ping(cx, payload)) {
+                Ok(()) => {}
+                Err(e) => {
+                    self.is_open = false;
+                    return Poll::Ready(Some(Err(e)));
+                }
+            }
+        }
+        // Pushes out queued pings, as well as pongs queued by tungstenite in reply to the server
+        if let Poll::Ready(Err(e)) = self.ws_stream.poll_flush_unpin(cx) {
+            return Poll::Ready(Some(Err(self.handle_ws_error(e))));
+        }
+        loop {
+            match self.ws_stream.poll_next_unpin(cx) {
+                Poll::Ready(Some(msg)) => {
+                    // Any message resets the timeout, even ones that aren't events (e.g. Ping to
+                    // keep alive)
+                    self.keepalive.on_message();
+                    let result = match msg {
+                        Ok(msg) => {
+                            if let Some(recorder) = &self.recorder {
+                                recorder.record(&msg);
+                            }
+                            self.handle_message(msg).transpose()
+                        }
+                        Err(e) => Some(Err(self.handle_ws_error(e))),
+                    };
+                    if let Some(result) = result {
+                        return Poll::Ready(Some(result));
+                    }
+                }
+                Poll::Ready(None) => {
+                    // The underlying stream has ended, there won't be any more messages
+                    self.is_open = false;
+                    return Poll::Ready(Some(Err(Error::Closed("Web Socket Closed".to_string()))));
+                }
+                Poll::Pending => {
+                    if let Poll::Ready(e) = self.keepalive.poll_idle(cx) {
+                        // This does not seem to be recoverable, or at least there are conditions
+                        // under which it does not recover. Indicate that the connection is closed
+                        // and a new one will have to be opened.
+                        self.is_open = false;
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+}
+
 enum AuthorizationType<'a> {
     None,
     Bearer(&'a str),
@@ -239,6 +375,7 @@ enum AuthorizationType<'a> {
 enum Body<T: Serialize> {
     Json(T),
     UrlEncoded(T),
+    Multipart(reqwest::multipart::Form),
 }
 
 const BODY_NONE: Option<Body<()>> = None;
@@ -246,16 +383,22 @@ const BODY_NONE: Option<Body<()>> = None;
 /// Implements low level REST requests to be used internally by the library
 #[derive(Clone)]
 struct RestClient {
+    rest_host_prefix: String,
     host_prefix: HashMap<String, String>,
     web_client: reqwest::Client,
+    retry_policy: RetryPolicy,
+    cassette: Option<Cassette>,
 }
 
 impl RestClient {
     /// Creates a new `RestClient`
     pub fn new() -> Self {
         Self {
+            rest_host_prefix: REST_HOST_PREFIX.to_string(),
             host_prefix: HashMap::new(),
             web_client: reqwest::Client::new(),
+            retry_policy: RetryPolicy::default(),
+            cassette: None,
         }
     }
 
@@ -264,21 +407,44 @@ impl RestClient {
      * high-level calls like "get_message"
      ******************************************************************/
 
-    async fn api_get<'a, T: DeserializeOwned>(
+    async fn api_get<T: DeserializeOwned>(
         &self,
         rest_method: &str,
         params: Option<impl Serialize>,
-        auth: AuthorizationType<'a>,
+        auth: AuthorizationType<'_>,
     ) -> Result<T, Error> {
         self.rest_api(reqwest::Method::GET, rest_method, auth, params, BODY_NONE)
             .await
     }
 
-    async fn api_delete<'a>(
+    /// Like `api_get`, but also returns the URL of the next page from the `Link` header, if any.
+    /// `rest_method` may also be an absolute URL, as returned for the next page.
+    async fn api_get_page<T: DeserializeOwned>(
+        &self,
+        rest_method: &str,
+        params: Option<impl Serialize>,
+        auth: AuthorizationType<'_>,
+    ) -> Result<(T, Option<String>), Error> {
+        let (result, headers) = self
+            .rest_api_with_headers(reqwest::Method::GET, rest_method, auth, params, BODY_NONE)
+            .await?;
+        let next = next_link(&headers);
+        if let Some(next) = &next {
+            // Don't let a response send the token (and the rest of the listing) elsewhere
+            if url::Url::parse(next).map(|next| next.origin()).ok() != self.origin(rest_method) {
+                return Err(Error::UnexpectedResponse(
+                    "Next page is on a different host than the listing",
+                ));
+            }
+        }
+        Ok((result, next))
+    }
+
+    async fn api_delete(
         &self,
         rest_method: &str,
         params: Option<impl Serialize>,
-        auth: AuthorizationType<'a>,
+        auth: AuthorizationType<'_>,
     ) -> Result<(), Error> {
         self.rest_api(
             reqwest::Method::DELETE,
@@ -290,12 +456,12 @@ impl RestClient {
         .await
     }
 
-    async fn api_post<'a, T: DeserializeOwned>(
+    async fn api_post<T: DeserializeOwned>(
         &self,
         rest_method: &str,
         body: impl Serialize,
         params: Option<impl Serialize>,
-        auth: AuthorizationType<'a>,
+        auth: AuthorizationType<'_>,
     ) -> Result<T, Error>
 where {
         self.rest_api(
@@ -308,12 +474,12 @@ where {
         .await
     }
 
-    async fn api_post_form_urlencoded<'a, T: DeserializeOwned>(
+    async fn api_post_form_urlencoded<T: DeserializeOwned>(
         &self,
         rest_method: &str,
         body: impl Serialize,
         params: Option<impl Serialize>,
-        auth: AuthorizationType<'a>,
+        auth: AuthorizationType<'_>,
     ) -> Result<T, Error> {
         self.rest_api(
             reqwest::Method::POST,
@@ -325,12 +491,39 @@ where {
         .await
     }
 
-    async fn api_put<'a, T: DeserializeOwned>(
+    async fn api_post_multipart<T: DeserializeOwned>(
+        &self,
+        rest_method: &str,
+        form: reqwest::multipart::Form,
+        auth: AuthorizationType<'_>,
+    ) -> Result<T, Error> {
+        self.rest_api(
+            reqwest::Method::POST,
+            rest_method,
+            auth,
+            None::<()>,
+            Some(Body::<()>::Multipart(form)),
+        )
+        .await
+    }
+
+    /// Request without params or body, for endpoints that don't return JSON.
+    async fn api_raw(
+        &self,
+        http_method: reqwest::Method,
+        rest_method: &str,
+        auth: AuthorizationType<'_>,
+    ) -> Result<reqwest::Response, Error> {
+        self.rest_api_raw(http_method, rest_method, auth, None::<()>, BODY_NONE)
+            .await
+    }
+
+    async fn api_put<T: DeserializeOwned>(
         &self,
         rest_method: &str,
         body: impl Serialize,
         params: Option<impl Serialize>,
-        auth: AuthorizationType<'a>,
+        auth: AuthorizationType<'_>,
     ) -> Result<T, Error> {
         self.rest_api(
             reqwest::Method::PUT,
@@ -350,13 +543,47 @@ where {
         params: Option<impl Serialize>,
         body: Option<Body<impl Serialize>>,
     ) -> Result<T, Error> {
-        let url_trimmed = url.split('?').next().unwrap_or(url);
-        let prefix = self
-            .host_prefix
-            .get(url_trimmed)
-            .map_or(REST_HOST_PREFIX, String::as_str);
-        let url = format!("{prefix}/{url}");
-        let mut request_builder = self.web_client.request(http_method, url);
+        self.rest_api_with_headers(http_method, url, auth, params, body)
+            .await
+            .map(|(result, _headers)| result)
+    }
+
+    async fn rest_api_with_headers<T: DeserializeOwned>(
+        &self,
+        http_method: reqwest::Method,
+        url: &str,
+        auth: AuthorizationType<'_>,
+        params: Option<impl Serialize>,
+        body: Option<Body<impl Serialize>>,
+    ) -> Result<(T, reqwest::header::HeaderMap), Error> {
+        let res = self
+            .rest_api_raw(http_method, url, auth, params, body)
+            .await?;
+        let headers = res.headers().clone();
+        let body = res.bytes().await?;
+        if body.is_empty() {
+            // e.g. 204 No Content from a DELETE
+            Ok((serde_json::from_slice(b"null")?, headers))
+        } else {
+            Ok((serde_json::from_slice(&body)?, headers))
+        }
+    }
+
+    /// Performs the request, with retries, returning the response for a 2xx status. The body is
+    /// left for the caller to read.
+    async fn rest_api_raw(
+        &self,
+        http_method: reqwest::Method,
+        url: &str,
+        auth: AuthorizationType<'_>,
+        params: Option<impl Serialize>,
+        body: Option<Body<impl Serialize>>,
+    ) -> Result<reqwest::Response, Error> {
+        if is_absolute(url) && !matches!(auth, AuthorizationType::None) && !self.is_trusted(url) {
+            return Err(Error::UntrustedUrl(url.to_string()));
+        }
+        let url = self.url(url);
+        let mut request_builder = self.web_client.request(http_method.clone(), url);
         if let Some(params) = params {
             request_builder = request_builder.query(&params);
         }
@@ -367,6 +594,9 @@ where {
             Some(Body::UrlEncoded(body)) => {
                 request_builder = request_builder.form(&body);
             }
+            Some(Body::Multipart(form)) => {
+                request_builder = request_builder.multipart(form);
+            }
             None => {}
         }
         match auth {
@@ -378,70 +608,226 @@ where {
                 request_builder = request_builder.basic_auth(username, Some(password));
             }
         }
-        let res = request_builder.send().await?;
-        Ok(res.json().await?)
+        let mut attempt = 1;
+        loop {
+            // Only fails for streaming bodies, which can't be replayed anyway
+            let Some(request) = request_builder.try_clone() else {
+                return self.send(request_builder).await;
+            };
+            match self.send(request).await {
+                Err(e) => match self.retry_policy.retry_delay(&e, attempt, &http_method) {
+                    Some(delay) => {
+                        debug!("Request failed ({e}), retrying in {delay:?}");
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                    }
+                    None => return Err(e),
+                },
+                result => return result,
+            }
+        }
+    }
+
+    /// Full URL for `rest_method`, which is either relative to its host prefix or already
+    /// absolute.
+    fn url(&self, rest_method: &str) -> String {
+        if is_absolute(rest_method) {
+            return rest_method.to_string();
+        }
+        let url_trimmed = rest_method.split('?').next().unwrap_or(rest_method);
+        let prefix = self
+            .host_prefix
+            .get(url_trimmed)
+            .unwrap_or(&self.rest_host_prefix);
+        format!("{prefix}/{rest_method}")
+    }
+
+    fn origin(&self, rest_method: &str) -> Option<url::Origin> {
+        url::Url::parse(&self.url(rest_method))
+            .ok()
+            .map(|url| url.origin())
+    }
+
+    /// Whether credentials may be sent to the absolute `url`: Webex hosts over HTTPS, and the
+    /// endpoints this client was configured with.
+    fn is_trusted(&self, url: &str) -> bool {
+        let Ok(url) = url::Url::parse(url) else {
+            return false;
+        };
+        let webex_host = url.host_str().is_some_and(|host| {
+            WEBEX_DOMAINS.iter().any(|domain| {
+                host.strip_suffix(domain)
+                    .is_some_and(|sub| sub.is_empty() || sub.ends_with('.'))
+            })
+        });
+        (webex_host && url.scheme() == "https")
+            || std::iter::once(&self.rest_host_prefix)
+                .chain(self.host_prefix.values())
+                .filter_map(|prefix| url::Url::parse(prefix).ok())
+                .any(|prefix| prefix.origin() == url.origin())
+    }
+
+    async fn send(
+        &self,
+        request_builder: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, Error> {
+        let res = match &self.cassette {
+            Some(cassette) => cassette.send(request_builder).await?,
+            None => request_builder.send().await?,
+        };
+        let status = res.status();
+        if !status.is_success() {
+            let retry_after = res
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after);
+            let body = res.bytes().await?;
+            return Err(Self::status_error(status, retry_after, &body));
+        }
+        Ok(res)
+    }
+
+    /// Converts a non-2xx response into the matching [`Error`] variant.
+    fn status_error(status: StatusCode, retry_after: Option<Duration>, body: &[u8]) -> Error {
+        let json = serde_json::from_slice::<serde_json::Value>(body).ok();
+        let reply = json
+            .as_ref()
+            .and_then(|json| ErrorReply::deserialize(json).ok());
+        let message = reply.as_ref().map_or_else(
+            || {
+                let text = String::from_utf8_lossy(body).trim().to_string();
+                (!text.is_empty()).then_some(text)
+            },
+            ErrorReply::description,
+        );
+        ApiError {
+            status,
+            message,
+            tracking_id: reply.and_then(|reply| reply.tracking_id),
+            body: json,
+            retry_after,
+        }
+        .into()
     }
 }
 
+fn is_absolute(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
+
+/// Parses a `Retry-After` header, which is either a number of seconds or an HTTP-date
+/// (RFC 9110 §10.2.3). A date in the past means the request can be retried straight away.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// Extracts the `rel="next"` URL from an RFC 5988 `Link` header.
+fn next_link(headers: &reqwest::header::HeaderMap) -> Option<String> {
+    headers
+        .get_all(reqwest::header::LINK)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|link| {
+            let mut parts = link.split(';');
+            let url = parts.next()?.trim();
+            let is_next = parts.any(|param| {
+                param
+                    .trim()
+                    .strip_prefix("rel=")
+                    .is_some_and(|rel| rel.trim_matches('"').split(' ').any(|r| r == "next"))
+            });
+            is_next.then(|| {
+                url.trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_string()
+            })
+        })
+}
+
 impl Webex {
     /// Constructs a new Webex Teams context from a token
     /// Tokens can be obtained when creating a bot, see <https://developer.webex.com/my-apps> for
     /// more information and to create your own Webex bots.
+    ///
+    /// The token is not checked, and failures to look up the WDM URL are only logged; use
+    /// [`try_new()`](Self::try_new) to find out about them straight away.
+    ///
+    /// # Panics
+    /// Panics if the HTTP client cannot be initialized (e.g. the TLS backend or the system
+    /// configuration cannot be loaded), like [`reqwest::Client::new`]. This is the only error
+    /// [`WebexBuilder::build()`] can return without [`validate_token`](WebexBuilder::validate_token).
     pub async fn new(token: &str) -> Self {
-        Self::new_with_device_name(DEFAULT_DEVICE_NAME, token).await
+        Self::builder(token)
+            .build()
+            .await
+            .expect("default HTTP client should build")
     }
 
     /// Constructs a new Webex Teams context from a token and a chosen name
     /// The name is used to identify the device/client with Webex api
+    ///
+    /// # Panics
+    /// Panics if the HTTP client cannot be initialized (e.g. the TLS backend or the system
+    /// configuration cannot be loaded), like [`reqwest::Client::new`]. This is the only error
+    /// [`WebexBuilder::build()`] can return without [`validate_token`](WebexBuilder::validate_token).
+    #[deprecated(
+        since = "0.11.0",
+        note = "Please use `Webex::builder(token).device_name(name).build()` instead"
+    )]
     pub async fn new_with_device_name(device_name: &str, token: &str) -> Self {
-        let mut client: RestClient = RestClient {
-            host_prefix: HashMap::new(),
-            web_client: reqwest::Client::new(),
-        };
+        Self::builder(token)
+            .device_name(device_name)
+            .build()
+            .await
+            .expect("default HTTP client should build")
+    }
+
+    /// Constructs a new Webex Teams context from a token, checking that the token is valid.
+    /// Use [`token_kind()`](Self::token_kind) to find out whether it belongs to a bot or a user.
+    ///
+    /// # Errors
+    /// As for [`WebexBuilder::build()`] with [`validate_token`](WebexBuilder::validate_token).
+    pub async fn try_new(token: &str) -> Result<Self, Error> {
+        Self::builder(token).validate_token(true).build().await
+    }
 
-        let mut hasher = DefaultHasher::new();
-        hash::Hash::hash_slice(token.as_bytes(), &mut hasher);
-        let id = hasher.finish();
+    /// The person the token belongs to, if the token was validated when creating the client.
+    /// Otherwise, see [`me()`](Self::me).
+    #[must_use]
+    pub const fn identity(&self) -> Option<&Person> {
+        self.identity.as_ref()
+    }
 
-        // Have to insert this before calling get_mercury_url() since it uses U2C for the catalog
-        // request.
-        client
-            .host_prefix
-            .insert("limited/catalog".to_string(), U2C_HOST_PREFIX.to_string());
-
-        let mut webex = Self {
-            id,
-            client,
-            token: token.to_string(),
-            device: DeviceData {
-                device_name: Some(DEFAULT_DEVICE_NAME.to_string()),
-                device_type: Some("DESKTOP".to_string()),
-                localized_model: Some("rust".to_string()),
-                model: Some(format!("rust-v{CRATE_VERSION}")),
-                name: Some(device_name.to_owned()),
-                system_name: Some(DEVICE_SYSTEM_NAME.to_string()),
-                system_version: Some(CRATE_VERSION.to_string()),
-                ..DeviceData::default()
-            },
-        };
+    /// Whether the token belongs to a bot or a user, if the token was validated when creating the
+    /// client.
+    #[must_use]
+    pub fn token_kind(&self) -> Option<TokenKind> {
+        self.identity
+            .as_ref()
+            .map(|person| TokenKind::from(&person.person_type))
+    }
 
-        let devices_url = match webex.get_mercury_url().await {
-            Ok(url) => {
-                trace!("Fetched mercury url {}", url);
-                url
-            }
-            Err(e) => {
-                debug!("Failed to fetch devices url, falling back to default");
-                debug!("Error: {:?}", e);
-                DEFAULT_REGISTRATION_HOST_PREFIX.to_string()
-            }
-        };
-        webex
-            .client
-            .host_prefix
-            .insert("devices".to_string(), devices_url);
+    /// Returns a [`WebexBuilder`] to configure endpoints, the device name and HTTP settings
+    /// before creating the client.
+    pub fn builder(token: &str) -> WebexBuilder {
+        WebexBuilder::new(token)
+    }
 
-        webex
+    /// Replace the [`RetryPolicy`] used for REST requests.
+    /// Use [`RetryPolicy::disabled()`] to turn automatic retries off.
+    pub const fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.client.retry_policy = retry_policy;
     }
 
     /// Get an event stream handle
@@ -451,28 +837,31 @@ impl Webex {
         // new one if needed
         async fn connect_device(s: &Webex, device: DeviceData) -> Result<WebexEventStream, Error> {
             trace!("Attempting connection with device named {:?}", device.name);
-            let Some(ws_url) = device.ws_url else {
-                return Err("Device has no ws_url".into());
+            let Some(mut ws_url) = s.mercury_url.clone().or(device.ws_url) else {
+                return Err(Error::UnexpectedResponse("Device has no ws_url"));
             };
+            let cassette = s.client.cassette.as_ref();
+            if let Some(cassette) = cassette.filter(|cassette| cassette.is_replaying()) {
+                ws_url = cassette.replay_stream().await?;
+            }
             let url = url::Url::parse(ws_url.as_str())
                 .map_err(|_| Error::from("Failed to parse ws_url"))?;
-            debug!("Connecting to {:?}", url);
+            debug!("Connecting to {url:?}");
             match connect_async(url.as_str()).await {
                 Ok((mut ws_stream, _response)) => {
-                    debug!("Connected to {}", url);
+                    debug!("Connected to {url}");
                     WebexEventStream::auth(&mut ws_stream, &s.token).await?;
                     debug!("Authenticated");
-                    let timeout = Duration::from_secs(20);
-                    Ok(WebexEventStream {
-                        ws_stream,
-                        timeout,
-                        is_open: true,
-                    })
+                    let mut stream = WebexEventStream::new(ws_stream, s.keepalive);
+                    stream.recorder = cassette
+                        .filter(|cassette| !cassette.is_replaying())
+                        .map(|cassette| cassette.record_stream(url.as_str()));
+                    Ok(stream)
                 }
                 Err(e) => {
-                    warn!("Failed to connect to {:?}: {:?}", url, e);
+                    warn!("Failed to connect to {url:?}: {e:?}");
                     Err(Error::Tungstenite(
-                        e,
+                        Arc::new(e),
                         "Failed to connect to ws_url".to_string(),
                     ))
                 }
@@ -486,7 +875,7 @@ impl Webex {
             .await?
             .iter()
             .filter(|d| d.name == self.device.name)
-            .inspect(|d| trace!("Kept device: {}", d))
+            .inspect(|d| trace!("Kept device: {d}"))
             .cloned()
             .collect();
 
@@ -509,57 +898,11 @@ impl Webex {
         connect_device(self, self.setup_devices().await?).await
     }
 
-    async fn get_mercury_url(&self) -> Result<String, Option<error::Error>> {
-        // Bit of a hacky workaround, error::Error does not implement clone
-        // TODO: this can be fixed by returning a Result<String, &error::Error>
-        lazy_static::lazy_static! {
-            static ref MERCURY_CACHE: Mutex<HashMap<u64, Result<String, ()>>> = Mutex::new(HashMap::new());
-        }
-        if let Ok(Some(result)) = MERCURY_CACHE
-            .lock()
-            .map(|cache| cache.get(&self.id).cloned())
-        {
-            trace!("Found mercury URL in cache!");
-            return result.map_err(|()| None);
-        }
-
-        let mercury_url = self.get_mercury_url_uncached().await;
-
-        if let Ok(mut cache) = MERCURY_CACHE.lock() {
-            let result = mercury_url.as_ref().map_or(Err(()), |url| Ok(url.clone()));
-            trace!("Saving mercury url to cache: {}=>{:?}", self.id, &result);
-            cache.insert(self.id, result);
-        }
-
-        mercury_url.map_err(Some)
-    }
-
-    async fn get_mercury_url_uncached(&self) -> Result<String, error::Error> {
-        // Steps:
-        // 1. Get org id by GET /v1/organizations
-        // 2. Get urls json from https://u2c.wbx2.com/u2c/api/v1/limited/catalog?orgId=[org id]
-        // 3. mercury url is urls["serviceLinks"]["wdm"]
-        //
-        // 4. Add caching because this doesn't change, and it can be slow
-
-        let orgs = self.list::<Organization>().await?;
-        if orgs.is_empty() {
-            return Err("Can't get mercury URL with no orgs".into());
-        }
-        let org_id = &orgs[0].id;
-        let api_url = "limited/catalog";
-        let params = [("format", "hostmap"), ("orgId", org_id.as_str())];
-        let catalogs = self
-            .client
-            .api_get::<CatalogReply>(
-                api_url,
-                Some(params),
-                AuthorizationType::Bearer(&self.token),
-            )
-            .await?;
-        let mercury_url = catalogs.service_links.wdm;
-
-        Ok(mercury_url)
+    /// Get an event stream handle that reconnects by itself when the connection is lost.
+    /// See [`ReconnectingEventStream`].
+    pub async fn reconnecting_event_stream(&self) -> Result<ReconnectingEventStream, Error> {
+        let stream = self.event_stream().await?;
+        Ok(ReconnectingEventStream::new(self.clone(), stream))
     }
 
     /// Get list of organizations
@@ -610,24 +953,36 @@ impl Webex {
     /// one per team).
     pub async fn get_all_rooms(&self) -> Result<Vec<Room>, Error> {
         let (mut all_rooms, teams) = try_join!(self.list(), self.list::<Team>())?;
-        let futures: Vec<_> = teams
+        let team_ids: Vec<_> = teams
             .into_iter()
-            .map(|team| {
-                let params = [("teamId", team.id)];
-                self.client.api_get::<ListResult<Room>>(
-                    Room::API_ENDPOINT,
-                    Some(params),
-                    AuthorizationType::Bearer(&self.token),
-                )
-            })
+            .map(|team| GlobalId::new_with_cluster_unchecked(GlobalIdType::Team, team.id, None))
             .collect();
-        let teams_rooms = try_join_all(futures).await?;
-        for room in teams_rooms {
-            all_rooms.extend(room.items);
+        let teams_rooms = try_join_all(team_ids.iter().map(|id| self.get_team_rooms(id))).await?;
+        for rooms in teams_rooms {
+            all_rooms.extend(rooms);
         }
         Ok(all_rooms)
     }
 
+    /// Get all rooms of a team, following pagination.
+    ///
+    /// # Errors
+    /// * [`Error::Throttled`] - returned on HTTP 423/429 with an optional Retry-After, once the
+    ///   [`RetryPolicy`] gives up.
+    /// * [`Error::NotFound`] - if the team doesn't exist, or the client isn't a member of it.
+    /// * [`Error::Authentication`] - if the token is invalid or expired.
+    /// * [`Error::Api`] - returned on any other non-2xx response.
+    /// * [`Error::Json`] - returned when a page cannot be deserialised. (If this happens, this is
+    ///   a library bug and should be reported.)
+    pub async fn get_team_rooms(&self, team_id: &GlobalId) -> Result<Vec<Room>, Error> {
+        self.list_stream_with_params::<Room>(RoomListParams {
+            team_id: Some(team_id.id()),
+            ..RoomListParams::default()
+        })
+        .try_collect()
+        .await
+    }
+
     /// Get available room
     #[deprecated(since = "0.6.3", note = "Please use `webex::get::<Room>(id)` instead")]
     pub async fn get_room(&self, id: &GlobalId) -> Result<Room, Error> {
@@ -651,12 +1006,16 @@ impl Webex {
     ///
     /// # Errors
     /// Types of errors returned:
-    /// * [`Error::Limited`] - returned on HTTP 423/429 with an optional Retry-After.
-    /// * [`Error::Status`] | [`Error::StatusText`] - returned when the request results in a non-200 code.
+    /// * [`Error::Throttled`] - returned on HTTP 423/429 with an optional Retry-After, once the
+    ///   [`RetryPolicy`] gives up.
+    /// * [`Error::NotFound`] - if the room or person to send to doesn't exist, or the client isn't a
+    ///   member of the room.
+    /// * [`Error::Authentication`] - if the token is invalid or expired.
+    /// * [`Error::Api`] - returned on any other non-2xx response, e.g. if
+    ///   `message` has no destination.
     /// * [`Error::Json`] - returned when your input object cannot be serialized, or the return
     ///   value cannot be deserialised. (If this happens, this is a library bug and should be
     ///   reported.)
-    /// * [`Error::UTF8`] - returned when the request returns non-UTF8 code.
     pub async fn send_message(&self, message: &MessageOut) -> Result<Message, Error> {
         self.client
             .api_post(
@@ -676,8 +1035,12 @@ impl Webex {
     ///
     /// # Errors
     /// Types of errors returned:
-    /// * [`Error::Limited`] - returned on HTTP 423/429 with an optional Retry-After.
-    /// * [`Error::Status`] | [`Error::StatusText`] - returned when the request results in a non-200 code.
+    /// * [`Error::Throttled`] - returned on HTTP 423/429 with an optional Retry-After, once the
+    ///   [`RetryPolicy`] gives up.
+    /// * [`Error::NotFound`] - if the message doesn't exist.
+    /// * [`Error::Authentication`] - if the token is invalid or expired.
+    /// * [`Error::Api`] - returned on any other non-2xx response, e.g. if the message
+    ///   wasn't sent by the caller.
     /// * [`Error::Json`] - returned when your input object cannot be serialized, or the return
     ///   value cannot be deserialised. (If this happens, this is a library bug and should be reported).
     pub async fn edit_message(
@@ -696,14 +1059,217 @@ impl Webex {
             .await
     }
 
+    /// Create a room. The caller becomes a member (and, if it is locked, the moderator) of it.
+    ///
+    /// # Errors
+    /// * [`Error::Throttled`] - returned on HTTP 423/429 with an optional Retry-After, once the
+    ///   [`RetryPolicy`] gives up.
+    /// * [`Error::NotFound`] - if `team_id` is set and the team doesn't exist.
+    /// * [`Error::Authentication`] - if the token is invalid or expired.
+    /// * [`Error::Api`] - returned on any other non-2xx response.
+    /// * [`Error::Json`] - returned when the return value cannot be deserialised. (If this
+    ///   happens, this is a library bug and should be reported.)
+    pub async fn create_room(&self, params: &RoomCreateParams<'_>) -> Result<Room, Error> {
+        self.client
+            .api_post(
+                "rooms",
+                params,
+                None::<()>,
+                AuthorizationType::Bearer(&self.token),
+            )
+            .await
+    }
+
+    /// Update a room: rename it, lock or unlock it, or move it into a team. Delete a room with
+    /// [`delete::<Room>()`](Self::delete).
+    ///
+    /// # Errors
+    /// * [`Error::Throttled`] - returned on HTTP 423/429 with an optional Retry-After, once the
+    ///   [`RetryPolicy`] gives up.
+    /// * [`Error::NotFound`] - if the room doesn't exist, or the client isn't a member of it.
+    /// * [`Error::Authentication`] - if the token is invalid or expired.
+    /// * [`Error::Api`] - returned on any other non-2xx response, e.g. if the room
+    ///   is locked and the caller isn't a moderator.
+    /// * [`Error::Json`] - returned when the return value cannot be deserialised. (If this
+    ///   happens, this is a library bug and should be reported.)
+    pub async fn update_room(
+        &self,
+        room_id: &GlobalId,
+        params: &RoomUpdateParams<'_>,
+    ) -> Result<Room, Error> {
+        let rest_method = format!("rooms/{}", room_id.id());
+        self.client
+            .api_put(
+                &rest_method,
+                params,
+                None::<()>,
+                AuthorizationType::Bearer(&self.token),
+            )
+            .await
+    }
+
+    /// Create a team. The caller becomes its moderator, and a "General" room is created in it.
+    ///
+    /// # Errors
+    /// * [`Error::Throttled`] - returned on HTTP 423/429 with an optional Retry-After, once the
+    ///   [`RetryPolicy`] gives up.
+    /// * [`Error::Authentication`] - if the token is invalid or expired.
+    /// * [`Error::Api`] - returned on any other non-2xx response.
+    /// * [`Error::Json`] - returned when the return value cannot be deserialised. (If this
+    ///   happens, this is a library bug and should be reported.)
+    pub async fn create_team(&self, params: &TeamParams<'_>) -> Result<Team, Error> {
+        self.client
+            .api_post(
+                "teams",
+                params,
+                None::<()>,
+                AuthorizationType::Bearer(&self.token),
+            )
+            .await
+    }
+
+    /// Rename a team or change its description. Delete a team (and all its rooms) with
+    /// [`delete::<Team>()`](Self::delete).
+    ///
+    /// # Errors
+    /// * [`Error::Throttled`] - returned on HTTP 423/429 with an optional Retry-After, once the
+    ///   [`RetryPolicy`] gives up.
+    /// * [`Error::NotFound`] - if the team doesn't exist, or the client isn't a member of it.
+    /// * [`Error::Authentication`] - if the token is invalid or expired.
+    /// * [`Error::Api`] - returned on any other non-2xx response.
+    /// * [`Error::Json`] - returned when the return value cannot be deserialised. (If this
+    ///   happens, this is a library bug and should be reported.)
+    pub async fn update_team(
+        &self,
+        team_id: &GlobalId,
+        params: &TeamParams<'_>,
+    ) -> Result<Team, Error> {
+        let rest_method = format!("teams/{}", team_id.id());
+        self.client
+            .api_put(
+                &rest_method,
+                params,
+                None::<()>,
+                AuthorizationType::Bearer(&self.token),
+            )
+            .await
+    }
+
+    /// Add a person to a team, optionally as a moderator. Remove them with
+    /// [`delete::<TeamMembership>()`](Self::delete).
+    ///
+    /// # Errors
+    /// * [`Error::Throttled`] - returned on HTTP 423/429 with an optional Retry-After, once the
+    ///   [`RetryPolicy`] gives up.
+    /// * [`Error::NotFound`] - if the team or the person doesn't exist.
+    /// * [`Error::Authentication`] - if the token is invalid or expired.
+    /// * [`Error::Api`] - returned on any other non-2xx response, e.g. if the
+    ///   person is already a member of the team.
+    /// * [`Error::Json`] - returned when the return value cannot be deserialised. (If this
+    ///   happens, this is a library bug and should be reported.)
+    pub async fn create_team_membership(
+        &self,
+        params: &TeamMembershipCreateParams<'_>,
+    ) -> Result<TeamMembership, Error> {
+        self.client
+            .api_post(
+                "team/memberships",
+                params,
+                None::<()>,
+                AuthorizationType::Bearer(&self.token),
+            )
+            .await
+    }
+
+    /// Make a team member a moderator of the team, or stop them being one.
+    ///
+    /// # Errors
+    /// * [`Error::Throttled`] - returned on HTTP 423/429 with an optional Retry-After, once the
+    ///   [`RetryPolicy`] gives up.
+    /// * [`Error::NotFound`] - if the team membership doesn't exist.
+    /// * [`Error::Authentication`] - if the token is invalid or expired.
+    /// * [`Error::Api`] - returned on any other non-2xx response, e.g. if the
+    ///   caller isn't a moderator of the team.
+    /// * [`Error::Json`] - returned when the return value cannot be deserialised. (If this
+    ///   happens, this is a library bug and should be reported.)
+    pub async fn update_team_membership(
+        &self,
+        membership_id: &GlobalId,
+        params: &TeamMembershipUpdateParams,
+    ) -> Result<TeamMembership, Error> {
+        let rest_method = format!("team/memberships/{}", membership_id.id());
+        self.client
+            .api_put(
+                &rest_method,
+                params,
+                None::<()>,
+                AuthorizationType::Bearer(&self.token),
+            )
+            .await
+    }
+
+    /// Add a person to a room, optionally as a moderator.
+    ///
+    /// # Errors
+    /// * [`Error::Throttled`] - returned on HTTP 423/429 with an optional Retry-After, once the
+    ///   [`RetryPolicy`] gives up.
+    /// * [`Error::NotFound`] - if the room or the person doesn't exist.
+    /// * [`Error::Authentication`] - if the token is invalid or expired.
+    /// * [`Error::Api`] - returned on any other non-2xx response, e.g. if the
+    ///   person is already a member of the room.
+    /// * [`Error::Json`] - returned when the return value cannot be deserialised. (If this
+    ///   happens, this is a library bug and should be reported.)
+    pub async fn create_membership(
+        &self,
+        params: &MembershipCreateParams<'_>,
+    ) -> Result<Membership, Error> {
+        self.client
+            .api_post(
+                "memberships",
+                params,
+                None::<()>,
+                AuthorizationType::Bearer(&self.token),
+            )
+            .await
+    }
+
+    /// Update a membership, e.g. to make the person a moderator of the room or stop them being
+    /// one. Remove a person from a room with [`delete::<Membership>()`](Self::delete).
+    ///
+    /// # Errors
+    /// * [`Error::Throttled`] - returned on HTTP 423/429 with an optional Retry-After, once the
+    ///   [`RetryPolicy`] gives up.
+    /// * [`Error::NotFound`] - if the membership doesn't exist.
+    /// * [`Error::Authentication`] - if the token is invalid or expired.
+    /// * [`Error::Api`] - returned on any other non-2xx response, e.g. if the caller
+    ///   isn't a moderator of the room.
+    /// * [`Error::Json`] - returned when the return value cannot be deserialised. (If this
+    ///   happens, this is a library bug and should be reported.)
+    pub async fn update_membership(
+        &self,
+        membership_id: &GlobalId,
+        params: &MembershipUpdateParams,
+    ) -> Result<Membership, Error> {
+        let rest_method = format!("memberships/{}", membership_id.id());
+        self.client
+            .api_put(
+                &rest_method,
+                params,
+                None::<()>,
+                AuthorizationType::Bearer(&self.token),
+            )
+            .await
+    }
+
     /// Get a resource from an ID
     /// # Errors
-    /// * [`Error::Limited`] - returned on HTTP 423/429 with an optional Retry-After.
-    /// * [`Error::Status`] | [`Error::StatusText`] - returned when the request results in a non-200 code.
-    /// * [`Error::Json`] - returned when your input object cannot be serialized, or the return
-    ///   value cannot be deserialised. (If this happens, this is a library bug and should be
-    ///   reported.)
-    /// * [`Error::UTF8`] - returned when the request returns non-UTF8 code.
+    /// * [`Error::Throttled`] - returned on HTTP 423/429 with an optional Retry-After, once the
+    ///   [`RetryPolicy`] gives up.
+    /// * [`Error::NotFound`] - if there is no resource with this ID, or it isn't visible to the caller.
+    /// * [`Error::Authentication`] - if the token is invalid or expired.
+    /// * [`Error::Api`] - returned on any other non-2xx response.
+    /// * [`Error::Json`] - returned when the return value cannot be deserialised. (If this
+    ///   happens, this is a library bug and should be reported.)
     pub async fn get<T: Gettable + DeserializeOwned>(&self, id: &GlobalId) -> Result<T, Error> {
         let rest_method = format!("{}/{}", T::API_ENDPOINT, id.id());
         self.client
@@ -728,6 +1294,9 @@ impl Webex {
     }
 
     /// List resources of a type
+    ///
+    /// Only returns the first page of results; use [`list_stream()`](Self::list_stream) or
+    /// [`list_all()`](Self::list_all) to get everything.
     pub async fn list<T: Gettable + DeserializeOwned>(&self) -> Result<Vec<T>, Error> {
         self.client
             .api_get::<ListResult<T>>(
@@ -754,6 +1323,69 @@ impl Webex {
             .map(|result| result.items)
     }
 
+    /// Stream all resources of a type.
+    ///
+    /// Unlike [`list()`](Self::list), which only returns the first page of results, this follows
+    /// the `Link: rel="next"` headers returned by the API. Pages are only requested once the
+    /// items of the previous page have been consumed. A next page on another host than the first
+    /// one is not followed; the stream ends with [`Error::UnexpectedResponse`] instead.
+    pub fn list_stream<T: Gettable + DeserializeOwned>(
+        &self,
+    ) -> impl Stream<Item = Result<T, Error>> + '_ {
+        self.paginate(Ok(T::API_ENDPOINT.to_string()))
+    }
+
+    /// Stream all resources of a type, with parameters. See [`list_stream()`](Self::list_stream).
+    pub fn list_stream_with_params<T: Gettable + DeserializeOwned>(
+        &self,
+        list_params: T::ListParams<'_>,
+    ) -> impl Stream<Item = Result<T, Error>> + '_ {
+        let first_page = serde_html_form::to_string(list_params)
+            .map(|query| {
+                if query.is_empty() {
+                    T::API_ENDPOINT.to_string()
+                } else {
+                    format!("{}?{query}", T::API_ENDPOINT)
+                }
+            })
+            .map_err(Error::from);
+        self.paginate(first_page)
+    }
+
+    /// Collect all resources of a type, following pagination, stopping after `max_items` items
+    /// if given.
+    pub async fn list_all<T: Gettable + DeserializeOwned>(
+        &self,
+        max_items: Option<usize>,
+    ) -> Result<Vec<T>, Error> {
+        self.list_stream()
+            .take(max_items.unwrap_or(usize::MAX))
+            .try_collect()
+            .await
+    }
+
+    fn paginate<T: DeserializeOwned>(
+        &self,
+        first_page: Result<String, Error>,
+    ) -> impl Stream<Item = Result<T, Error>> + '_ {
+        stream::try_unfold(Some(first_page), move |page| async move {
+            let Some(url) = page else {
+                return Ok::<_, Error>(None);
+            };
+            let (result, next) = self
+                .client
+                .api_get_page::<ListResult<T>>(
+                    &url?,
+                    None::<()>,
+                    AuthorizationType::Bearer(&self.token),
+                )
+                .await?;
+            Ok(Some((result.items, next.map(Ok))))
+        })
+        .map_ok(|items| stream::iter(items.into_iter().map(Ok)))
+        .try_flatten()
+    }
+
     async fn get_devices(&self) -> Result<Vec<DeviceData>, Error> {
         match self
             .client
@@ -770,18 +1402,12 @@ impl Webex {
                 debug!("Chaining one-time device setup from devices query");
                 self.setup_devices().await.map(|device| vec![device])
             }
-            Err(e) => match e {
-                Error::Status(s) | Error::StatusText(s, _) => {
-                    if s == StatusCode::NOT_FOUND {
-                        debug!("No devices found, creating new one");
-                        self.setup_devices().await.map(|device| vec![device])
-                    } else {
-                        Err(e)
-                    }
-                }
-                Error::Limited(_, _) => Err(e),
-                _ => Err(format!("Can't decode devices reply: {e}").into()),
-            },
+            Err(Error::NotFound(_)) => {
+                debug!("No devices found, creating new one");
+                self.setup_devices().await.map(|device| vec![device])
+            }
+            Err(e @ Error::Json(_)) => Err(format!("Can't decode devices reply: {e}").into()),
+            Err(e) => Err(e),
         }
     }
 
@@ -868,3 +1494,571 @@ impl MessageOut {
         self
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn status_error_parsing() {
+        let body = br#"{"message":"The requested resource could not be found.","errors":[{"description":"The requested resource could not be found."}],"trackingId":"ROUTER_1234"}"#;
+        match RestClient::status_error(StatusCode::NOT_FOUND, None, body) {
+            Error::NotFound(error) => {
+                assert_eq!(error.status, StatusCode::NOT_FOUND);
+                assert_eq!(
+                    error.message.as_deref(),
+                    Some("The requested resource could not be found.")
+                );
+                assert_eq!(error.tracking_id.as_deref(), Some("ROUTER_1234"));
+                assert_eq!(error.body.unwrap()["trackingId"], "ROUTER_1234");
+            }
+            e => panic!("expected NotFound, got {e:?}"),
+        }
+
+        // OAuth endpoints use a different error body
+        let body = br#"{"error":"authorization_pending","error_description":"The user has not yet authorized"}"#;
+        let err = RestClient::status_error(StatusCode::PRECONDITION_REQUIRED, None, body);
+        assert_eq!(err.status(), Some(StatusCode::PRECONDITION_REQUIRED));
+        assert_eq!(
+            err.api_error().unwrap().message.as_deref(),
+            Some("The user has not yet authorized")
+        );
+
+        let err = RestClient::status_error(StatusCode::BAD_GATEWAY, None, b"");
+        assert!(
+            matches!(&err, Error::Api(error) if **error == ApiError::new(StatusCode::BAD_GATEWAY))
+        );
+        assert!(err.is_retryable());
+    }
+
+    /// Serves the canned `(status line, extra headers, body)` responses in order, one per
+    /// connection, and returns the address along with the request lines that were received.
+    /// `$ADDR` in the headers is replaced with the server address.
+    pub async fn mock_server<B: Into<String> + Send + 'static>(
+        responses: Vec<(&'static str, &'static str, B)>,
+    ) -> (String, std::sync::Arc<std::sync::Mutex<Vec<String>>>) {
+        let (url, requests, _) = mock_server_with_bodies(responses).await;
+        (url, requests)
+    }
+
+    /// Like [`mock_server()`], but also returns the request bodies, lossily decoded as UTF-8.
+    pub async fn mock_server_with_bodies<B: Into<String> + Send + 'static>(
+        responses: Vec<(&'static str, &'static str, B)>,
+    ) -> (
+        String,
+        std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+        std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+    ) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let bodies = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        let seen_bodies = bodies.clone();
+        tokio::spawn(async move {
+            for (status, headers, body) in responses {
+                let headers = headers.replace("$ADDR", &addr.to_string());
+                let body: String = body.into();
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 8192];
+                let mut len = 0;
+                // Read the headers, plus the body if there is one
+                loop {
+                    len += socket.read(&mut buf[len..]).await.unwrap();
+                    let request = String::from_utf8_lossy(&buf[..len]).to_string();
+                    if let Some(header_end) = request.find("\r\n\r\n") {
+                        let content_length = request
+                            .lines()
+                            .find_map(|l| l.strip_prefix("content-length: "))
+                            .map_or(0, |l| l.parse::<usize>().unwrap());
+                        if len >= header_end + 4 + content_length {
+                            seen.lock()
+                                .unwrap()
+                                .push(request.lines().next().unwrap().to_string());
+                            seen_bodies
+                                .lock()
+                                .unwrap()
+                                .push(request[header_end + 4..].to_string());
+                            break;
+                        }
+                    }
+                }
+                let response = format!(
+                    "HTTP/1.1 {status}\r\n{headers}content-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (format!("http://{addr}"), requests, bodies)
+    }
+
+    /// Websocket server accepting one connection per script. Each connection answers the
+    /// authorization message with a pong, sends the scripted messages, then stays open until the
+    /// client goes away. If `answer_pings` is false, it stops reading, so pings go unanswered as
+    /// on a half-open connection. Returns the `ws://` URL.
+    pub async fn mock_websocket(scripts: Vec<Vec<TMessage>>, answer_pings: bool) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            for script in scripts {
+                let (socket, _) = listener.accept().await.unwrap();
+                let mut ws = tokio_tungstenite::accept_async(socket).await.unwrap();
+                let auth = ws.next().await.unwrap().unwrap();
+                assert!(auth.to_text().unwrap().contains("authorization"));
+                ws.send(TMessage::Pong(Vec::new())).await.unwrap();
+                for msg in script {
+                    ws.send(msg).await.unwrap();
+                }
+                tokio::spawn(async move {
+                    if answer_pings {
+                        while let Some(Ok(_)) = ws.next().await {}
+                    } else {
+                        tokio::time::sleep(Duration::from_secs(60)).await;
+                    }
+                });
+            }
+        });
+        format!("ws://{addr}")
+    }
+
+    /// A minimal event as sent over the websocket.
+    pub fn event_message(event_type: &str) -> TMessage {
+        let event = Event {
+            id: "1".to_string(),
+            data: EventData {
+                event_type: event_type.to_string(),
+                ..EventData::default()
+            },
+            ..Event::default()
+        };
+        TMessage::Binary(serde_json::to_vec(&event).unwrap())
+    }
+
+    /// `GET /devices` reply listing a single device with the default name, at `ws_url`.
+    pub fn devices_reply(ws_url: &str) -> String {
+        format!(r#"{{"devices":[{{"name":"{DEFAULT_DEVICE_NAME}","webSocketUrl":"{ws_url}"}}]}}"#)
+    }
+
+    fn mock_client(prefix: String) -> RestClient {
+        let mut client = RestClient::new();
+        client.host_prefix.insert("test".to_string(), prefix);
+        client.retry_policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        };
+        client
+    }
+
+    #[tokio::test]
+    async fn retries_throttled_and_failed_get() {
+        let (prefix, requests) = mock_server(vec![
+            ("503 Service Unavailable", "", ""),
+            ("429 Too Many Requests", "retry-after: 0\r\n", ""),
+            ("200 OK", "", r#"{"id":"1"}"#),
+        ])
+        .await;
+        let client = mock_client(prefix);
+        let reply: serde_json::Value = client
+            .api_get("test", None::<()>, AuthorizationType::None)
+            .await
+            .unwrap();
+        assert_eq!(reply["id"], "1");
+        assert_eq!(requests.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn retries_throttled_post_only() {
+        let (prefix, requests) = mock_server(vec![
+            ("429 Too Many Requests", "retry-after: 0\r\n", ""),
+            ("200 OK", "", r#"{"id":"1"}"#),
+            ("502 Bad Gateway", "", ""),
+        ])
+        .await;
+        let client = mock_client(prefix);
+        let reply: serde_json::Value = client
+            .api_post("test", "body", None::<()>, AuthorizationType::None)
+            .await
+            .unwrap();
+        assert_eq!(reply["id"], "1");
+
+        let err = client
+            .api_post::<serde_json::Value>("test", "body", None::<()>, AuthorizationType::None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::BAD_GATEWAY));
+        assert_eq!(*requests.lock().unwrap(), ["POST /test HTTP/1.1"; 3]);
+    }
+
+    #[test]
+    fn credentials_stay_on_trusted_hosts() {
+        let client = mock_client("http://127.0.0.1:1234".to_string());
+        assert!(client.is_trusted("https://webexapis.com/v1/contents/abc"));
+        assert!(client.is_trusted("https://wdm-a.wbx2.com/wdm/api/v1/devices"));
+        assert!(client.is_trusted("http://127.0.0.1:1234/test?cursor=2"));
+        assert!(!client.is_trusted("http://webexapis.com/v1/contents/abc"));
+        assert!(!client.is_trusted("https://evilwebex.com/"));
+        assert!(!client.is_trusted("https://webex.com.example.com/"));
+        assert!(!client.is_trusted("http://127.0.0.1:4321/test"));
+    }
+
+    #[test]
+    fn link_header_parsing() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        assert_eq!(next_link(&headers), None);
+        headers.insert(
+            reqwest::header::LINK,
+            r#"<https://webexapis.com/v1/rooms?cursor=abc>; rel="prev", <https://webexapis.com/v1/rooms?cursor=def>; rel="next""#
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(
+            next_link(&headers).as_deref(),
+            Some("https://webexapis.com/v1/rooms?cursor=def")
+        );
+    }
+
+    #[tokio::test]
+    async fn list_follows_next_links() {
+        let (prefix, requests) = mock_server(vec![
+            (
+                "200 OK",
+                "link: <http://$ADDR/teams?cursor=2>; rel=\"next\"\r\n",
+                r#"{"items":[{"id":"1","created":""},{"id":"2","created":""}]}"#,
+            ),
+            ("200 OK", "", r#"{"items":[{"id":"3","created":""}]}"#),
+        ])
+        .await;
+        let webex = Webex::builder("token")
+            .rest_url(prefix)
+            .wdm_url("http://localhost")
+            .build()
+            .await
+            .unwrap();
+        let teams: Vec<Team> = webex.list_stream().try_collect().await.unwrap();
+        let ids: Vec<_> = teams.iter().map(|team| team.id.as_str()).collect();
+        assert_eq!(ids, ["1", "2", "3"]);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            ["GET /teams HTTP/1.1", "GET /teams?cursor=2 HTTP/1.1"]
+        );
+    }
+
+    #[tokio::test]
+    async fn list_stays_on_the_same_host() {
+        let (prefix, requests) = mock_server(vec![(
+            "200 OK",
+            "link: <https://webexapis.com/v1/teams?cursor=2>; rel=\"next\"\r\n",
+            r#"{"items":[{"id":"1","created":""}]}"#,
+        )])
+        .await;
+        let webex = Webex::builder("token")
+            .rest_url(prefix)
+            .wdm_url("http://localhost")
+            .build()
+            .await
+            .unwrap();
+        let err = webex.list_all::<Team>(None).await.unwrap_err();
+        assert!(matches!(err, Error::UnexpectedResponse(_)), "{err:?}");
+        assert_eq!(*requests.lock().unwrap(), ["GET /teams HTTP/1.1"]);
+    }
+
+    #[tokio::test]
+    async fn room_lifecycle() {
+        let room = r#"{"id":"r","title":"Incident","type":"group","isLocked":true,"teamId":"t","lastActivity":"","creatorId":"me","created":""}"#;
+        let (prefix, requests) = mock_server(vec![
+            ("200 OK", "", room),
+            ("200 OK", "", room),
+            ("204 No Content", "", ""),
+        ])
+        .await;
+        let webex = Webex::builder("token")
+            .rest_url(prefix)
+            .wdm_url("http://localhost")
+            .build()
+            .await
+            .unwrap();
+        let room = webex
+            .create_room(&RoomCreateParams {
+                title: "Incident",
+                team_id: Some("t"),
+                is_locked: Some(true),
+                ..RoomCreateParams::default()
+            })
+            .await
+            .unwrap();
+        let id = GlobalId::new_with_cluster_unchecked(GlobalIdType::Room, room.id, None);
+        let room = webex
+            .update_room(
+                &id,
+                &RoomUpdateParams {
+                    title: "Incident (resolved)",
+                    is_read_only: Some(true),
+                    ..RoomUpdateParams::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert!(room.is_locked);
+        webex.delete::<Room>(&id).await.unwrap();
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                "POST /rooms HTTP/1.1",
+                "PUT /rooms/r HTTP/1.1",
+                "DELETE /rooms/r HTTP/1.1",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn teams() {
+        let (prefix, requests) = mock_server(vec![
+            ("200 OK", "", r#"{"id":"t","name":"Project","created":""}"#),
+            (
+                "200 OK",
+                "",
+                r#"{"id":"t","name":"Project X","created":""}"#,
+            ),
+            (
+                "200 OK",
+                "",
+                r#"{"items":[{"id":"tm","teamId":"t","isModerator":false}]}"#,
+            ),
+            (
+                "200 OK",
+                "",
+                r#"{"id":"tm2","teamId":"t","isModerator":false}"#,
+            ),
+            (
+                "200 OK",
+                "",
+                r#"{"id":"tm2","teamId":"t","isModerator":true}"#,
+            ),
+            ("204 No Content", "", ""),
+        ])
+        .await;
+        let webex = Webex::builder("token")
+            .rest_url(prefix)
+            .wdm_url("http://localhost")
+            .build()
+            .await
+            .unwrap();
+        let team = webex
+            .create_team(&TeamParams {
+                name: "Project",
+                description: None,
+            })
+            .await
+            .unwrap();
+        let team_id = GlobalId::new_with_cluster_unchecked(GlobalIdType::Team, team.id, None);
+        let team = webex
+            .update_team(
+                &team_id,
+                &TeamParams {
+                    name: "Project X",
+                    description: None,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(team.name.as_deref(), Some("Project X"));
+        let members: Vec<TeamMembership> = webex
+            .list_with_params(TeamMembershipListParams {
+                team_id: "t",
+                max: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(members.len(), 1);
+        let member = webex
+            .create_team_membership(&TeamMembershipCreateParams {
+                team_id: "t",
+                person_email: Some("a@example.com"),
+                ..TeamMembershipCreateParams::default()
+            })
+            .await
+            .unwrap();
+        let member_id =
+            GlobalId::new_with_cluster_unchecked(GlobalIdType::TeamMembership, member.id, None);
+        let member = webex
+            .update_team_membership(
+                &member_id,
+                &TeamMembershipUpdateParams { is_moderator: true },
+            )
+            .await
+            .unwrap();
+        assert!(member.is_moderator);
+        webex.delete::<TeamMembership>(&member_id).await.unwrap();
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                "POST /teams HTTP/1.1",
+                "PUT /teams/t HTTP/1.1",
+                "GET /team/memberships?teamId=t HTTP/1.1",
+                "POST /team/memberships HTTP/1.1",
+                "PUT /team/memberships/tm2 HTTP/1.1",
+                "DELETE /team/memberships/tm2 HTTP/1.1",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn memberships() {
+        let (prefix, requests) = mock_server(vec![
+            (
+                "200 OK",
+                "",
+                r#"{"items":[{"id":"1","roomId":"r","personEmail":"a@example.com","isModerator":true}]}"#,
+            ),
+            ("200 OK", "", r#"{"id":"2","roomId":"r","isModerator":false}"#),
+            ("200 OK", "", r#"{"id":"2","roomId":"r","isModerator":true}"#),
+        ])
+        .await;
+        let webex = Webex::builder("token")
+            .rest_url(prefix)
+            .wdm_url("http://localhost")
+            .build()
+            .await
+            .unwrap();
+        let memberships: Vec<Membership> = webex
+            .list_with_params(MembershipListParams {
+                room_id: Some("r"),
+                person_email: Some("a@example.com"),
+                ..MembershipListParams::default()
+            })
+            .await
+            .unwrap();
+        assert!(memberships[0].is_moderator);
+        let created = webex
+            .create_membership(&MembershipCreateParams {
+                room_id: "r",
+                person_email: Some("b@example.com"),
+                ..MembershipCreateParams::default()
+            })
+            .await
+            .unwrap();
+        assert!(!created.is_moderator);
+        let id = GlobalId::new_with_cluster_unchecked(GlobalIdType::Membership, created.id, None);
+        let updated = webex
+            .update_membership(
+                &id,
+                &MembershipUpdateParams {
+                    is_moderator: true,
+                    ..MembershipUpdateParams::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert!(updated.is_moderator);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                "GET /memberships?roomId=r&personEmail=a%40example.com HTTP/1.1",
+                "POST /memberships HTTP/1.1",
+                "PUT /memberships/2 HTTP/1.1",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn event_stream_combinators() {
+        let ws_url = mock_websocket(
+            vec![vec![
+                event_message("status.start_typing"),
+                TMessage::Ping(Vec::new()),
+                event_message("conversation.highlight"),
+                TMessage::Close(None),
+            ]],
+            true,
+        )
+        .await;
+        let (wdm_url, _) = mock_server(vec![("200 OK", "", devices_reply(&ws_url))]).await;
+        let webex = Webex::builder("token")
+            .wdm_url(wdm_url)
+            .build()
+            .await
+            .unwrap();
+        let stream = webex.event_stream().await.unwrap();
+        let results: Vec<_> = stream.collect().await;
+        assert_eq!(results.len(), 3);
+        let event_types: Vec<_> = results[..2]
+            .iter()
+            .map(|event| event.as_ref().unwrap().data.event_type.as_str())
+            .collect();
+        assert_eq!(
+            event_types,
+            ["status.start_typing", "conversation.highlight"]
+        );
+        assert!(matches!(results[2], Err(Error::Closed(_))));
+    }
+
+    async fn keepalive_stream(answer_pings: bool) -> WebexEventStream {
+        let ws_url = mock_websocket(vec![vec![]], answer_pings).await;
+        let (wdm_url, _) = mock_server(vec![("200 OK", "", devices_reply(&ws_url))]).await;
+        let webex = Webex::builder("token")
+            .wdm_url(wdm_url)
+            .keepalive(KeepaliveSettings {
+                idle_timeout: Duration::from_millis(150),
+                ping_interval: Some(Duration::from_millis(40)),
+                max_missed_pongs: 2,
+            })
+            .build()
+            .await
+            .unwrap();
+        webex.event_stream().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn keepalive_pings_keep_quiet_stream_open() {
+        let mut stream = keepalive_stream(true).await;
+        // Longer than the idle timeout, but pongs count as activity
+        let next = tokio::time::timeout(Duration::from_millis(400), stream.next()).await;
+        assert!(next.is_err(), "expected no event, got {next:?}");
+        assert!(stream.is_open);
+        let health = stream.health();
+        assert!(health.pings_sent >= 3);
+        assert!(health.pongs_received >= 2);
+        assert!(health.last_pong_latency.is_some());
+        assert_eq!(health.missed_pongs, 0);
+    }
+
+    #[tokio::test]
+    async fn keepalive_detects_missed_pongs() {
+        let mut stream = keepalive_stream(false).await;
+        let err = stream.next().await.unwrap_err();
+        assert!(err.to_string().contains("no pong received"), "{err}");
+        assert!(!stream.is_open);
+        assert_eq!(stream.health().missed_pongs, 2);
+    }
+
+    #[test]
+    fn status_error_limited() {
+        let err = RestClient::status_error(
+            StatusCode::TOO_MANY_REQUESTS,
+            Some(Duration::from_secs(30)),
+            b"{}",
+        );
+        assert!(matches!(err, Error::Throttled(_)));
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(30)));
+        let err = RestClient::status_error(StatusCode::LOCKED, None, b"");
+        assert!(matches!(err, Error::Throttled(_)));
+        assert_eq!(err.retry_after(), None);
+    }
+
+    #[test]
+    fn retry_after_formats() {
+        assert_eq!(parse_retry_after(" 120 "), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        let later = (chrono::Utc::now() + chrono::Duration::seconds(90)).to_rfc2822();
+        let delay = parse_retry_after(&later).unwrap();
+        assert!(delay > Duration::from_secs(80) && delay <= Duration::from_secs(90));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+}
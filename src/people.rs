@@ -0,0 +1,215 @@
+#![deny(missing_docs)]
+//! Looking up people: the caller, and people by email address
+
+use crate::{error::Error, AuthorizationType, Person, PersonListParams, PersonType, Webex};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// How long a person found by [`Webex::person_by_email()`] is reused before it is looked up
+/// again, unless configured with
+/// [`WebexBuilder::person_cache()`](crate::WebexBuilder::person_cache).
+pub const DEFAULT_PERSON_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// How many people [`Webex::person_by_email()`] keeps at most, unless configured with
+/// [`WebexBuilder::person_cache()`](crate::WebexBuilder::person_cache).
+pub const DEFAULT_PERSON_CACHE_SIZE: usize = 1000;
+
+/// People found by [`Webex::person_by_email()`], keyed by lowercase email address. Shared between
+/// clones of a client.
+#[derive(Debug)]
+pub struct PersonCache {
+    ttl: Duration,
+    capacity: usize,
+    people: Mutex<HashMap<String, (Person, Instant)>>,
+}
+
+impl PersonCache {
+    pub fn new(ttl: Duration, capacity: usize) -> Arc<Self> {
+        Arc::new(Self {
+            ttl,
+            capacity,
+            people: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn get(&self, key: &str) -> Option<Person> {
+        let people = self.people.lock().unwrap_or_else(PoisonError::into_inner);
+        people
+            .get(key)
+            .filter(|(_, fetched_at)| fetched_at.elapsed() < self.ttl)
+            .map(|(person, _)| person.clone())
+    }
+
+    fn insert(&self, key: String, person: Person) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut people = self.people.lock().unwrap_or_else(PoisonError::into_inner);
+        if people.len() >= self.capacity && !people.contains_key(&key) {
+            people.retain(|_, (_, fetched_at)| fetched_at.elapsed() < self.ttl);
+            if people.len() >= self.capacity {
+                let oldest = people
+                    .iter()
+                    .min_by_key(|(_, (_, fetched_at))| *fetched_at)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    people.remove(&oldest);
+                }
+            }
+        }
+        people.insert(key, (person, Instant::now()));
+    }
+
+    fn remove(&self, key: &str) {
+        self.people
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(key);
+    }
+}
+
+/// Kind of account an access token belongs to, see [`Webex::token_kind()`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TokenKind {
+    /// A bot account
+    Bot,
+    /// A person, including integrations acting on behalf of one
+    User,
+    /// A guest user
+    Guest,
+}
+
+impl From<&PersonType> for TokenKind {
+    fn from(person_type: &PersonType) -> Self {
+        match person_type {
+            PersonType::Bot => Self::Bot,
+            PersonType::AppUser => Self::Guest,
+            PersonType::Person | PersonType::Unknown(_) => Self::User,
+        }
+    }
+}
+
+impl Webex {
+    /// Get the person the token belongs to, e.g. to find out the email address or ID of a bot.
+    ///
+    /// # Errors
+    /// * [`Error::Throttled`] - returned on HTTP 423/429 with an optional Retry-After, once the
+    ///   [`RetryPolicy`](crate::retry::RetryPolicy) gives up.
+    /// * [`Error::Authentication`] - if the token is invalid or expired.
+    /// * [`Error::Api`] - returned on any other non-2xx response.
+    /// * [`Error::Json`] - returned when the response cannot be deserialised.
+    pub async fn me(&self) -> Result<Person, Error> {
+        self.client
+            .api_get(
+                "people/me",
+                None::<()>,
+                AuthorizationType::Bearer(&self.token),
+            )
+            .await
+    }
+
+    /// Find a person by email address, or `None` if there is no such person.
+    ///
+    /// People that are found are cached (by default for [`DEFAULT_PERSON_TTL`], and up to
+    /// [`DEFAULT_PERSON_CACHE_SIZE`] people), so resolving the same address again (for instance
+    /// the author of every message in a busy room) doesn't cost a request. Fields that change
+    /// often, such as the presence `status`, may be as old as the TTL; use
+    /// [`get::<Person>()`](Self::get) for current ones, or
+    /// [`forget_person()`](Self::forget_person) to drop a stale entry.
+    ///
+    /// # Errors
+    /// As for [`me()`](Self::me).
+    pub async fn person_by_email(&self, email: &str) -> Result<Option<Person>, Error> {
+        let key = email.to_lowercase();
+        if let Some(person) = self.people.get(&key) {
+            return Ok(Some(person));
+        }
+        let person = self
+            .list_with_params::<Person>(PersonListParams {
+                email: Some(email),
+                ..PersonListParams::default()
+            })
+            .await?
+            .into_iter()
+            .next();
+        if let Some(person) = &person {
+            self.people.insert(key, person.clone());
+        }
+        Ok(person)
+    }
+
+    /// Remove a person from the cache used by [`person_by_email()`](Self::person_by_email).
+    pub fn forget_person(&self, email: &str) {
+        self.people.remove(&email.to_lowercase());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::mock_server;
+
+    #[tokio::test]
+    async fn caches_people_by_email() {
+        let alice = r#"{"items":[{"id":"a","emails":["alice@example.com"],"displayName":"Alice","type":"person"}]}"#;
+        let (prefix, requests) = mock_server(vec![
+            ("200 OK", "", alice.to_string()),
+            ("200 OK", "", r#"{"items":[]}"#.to_string()),
+            (
+                "200 OK",
+                "",
+                r#"{"id":"b","displayName":"Bot","type":"bot"}"#.to_string(),
+            ),
+        ])
+        .await;
+        let webex = Webex::builder("token")
+            .rest_url(prefix)
+            .wdm_url("http://localhost")
+            .build()
+            .await
+            .unwrap();
+
+        let alice = webex.person_by_email("alice@example.com").await.unwrap();
+        assert_eq!(alice.unwrap().display_name, "Alice");
+        let alice = webex.clone().person_by_email("Alice@Example.com").await;
+        assert_eq!(alice.unwrap().unwrap().id, "a");
+        assert_eq!(
+            webex.person_by_email("bob@example.com").await.unwrap(),
+            None
+        );
+        assert_eq!(
+            webex.me().await.unwrap().person_type,
+            crate::PersonType::Bot
+        );
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                "GET /people?email=alice%40example.com HTTP/1.1",
+                "GET /people?email=bob%40example.com HTTP/1.1",
+                "GET /people/me HTTP/1.1",
+            ]
+        );
+    }
+
+    #[test]
+    fn cache_expires_and_stays_bounded() {
+        let person = |id: &str| Person {
+            id: id.to_string(),
+            ..Person::default()
+        };
+        let cache = PersonCache::new(Duration::from_secs(60), 2);
+        cache.insert("a".to_string(), person("a"));
+        cache.insert("b".to_string(), person("b"));
+        cache.insert("c".to_string(), person("c"));
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("c").unwrap().id, "c");
+        assert_eq!(cache.people.lock().unwrap().len(), 2);
+
+        let cache = PersonCache::new(Duration::ZERO, 2);
+        cache.insert("a".to_string(), person("a"));
+        assert_eq!(cache.get("a"), None);
+    }
+}
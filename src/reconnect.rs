@@ -0,0 +1,208 @@
+#![deny(missing_docs)]
+//! Event stream that reconnects by itself when the websocket connection is lost
+
+use crate::{error::Error, retry::RetryPolicy, Event, StreamHealth, Webex, WebexEventStream};
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use std::time::Duration;
+
+/// Item returned by [`ReconnectingEventStream::next()`].
+#[derive(Clone, Debug)]
+pub enum StreamEvent {
+    /// An event received from Webex.
+    Event(Box<Event>),
+    /// The connection was lost and has been re-established. Events sent while disconnected are
+    /// not replayed, so this is the time to backfill anything that may have been missed (e.g. by
+    /// listing messages created after `disconnected_at`).
+    Reconnected {
+        /// When the connection was found to be lost.
+        disconnected_at: DateTime<Utc>,
+        /// Number of connection attempts it took to reconnect.
+        attempts: u32,
+    },
+}
+
+/// Wraps a [`WebexEventStream`], replacing it with a fresh connection whenever it closes.
+///
+/// Reconnecting goes through [`Webex::event_stream()`], so the WDM device is re-selected (or
+/// re-registered if it no longer exists) and the new websocket is authenticated again. Attempts
+/// are spaced out according to the [`RetryPolicy`] backoff.
+pub struct ReconnectingEventStream {
+    webex: Webex,
+    stream: Option<WebexEventStream>,
+    retry_policy: RetryPolicy,
+    disconnected_at: Option<DateTime<Utc>>,
+}
+
+impl ReconnectingEventStream {
+    pub(crate) fn new(webex: Webex, stream: WebexEventStream) -> Self {
+        Self {
+            webex,
+            stream: Some(stream),
+            retry_policy: RetryPolicy {
+                max_attempts: 15,
+                initial_backoff: Duration::from_secs(1),
+                max_backoff: Duration::from_secs(60),
+                ..RetryPolicy::default()
+            },
+            disconnected_at: None,
+        }
+    }
+
+    /// Replace the backoff used between reconnection attempts. `max_attempts` is the number of
+    /// attempts made before [`next()`](Self::next) gives up and returns the last error (the next
+    /// call will start over). `retry_non_idempotent` is ignored.
+    ///
+    /// Defaults to 15 attempts, backing off from 1 second up to 1 minute, which gives up after
+    /// roughly 5 to 10 minutes. Errors that retrying won't fix, such as a rejected token, are
+    /// returned straight away regardless of the policy.
+    pub const fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    /// True if there is currently an open websocket connection.
+    #[must_use]
+    pub fn is_connected(&self) -> bool {
+        self.stream.as_ref().is_some_and(|stream| stream.is_open)
+    }
+
+    /// Health of the current connection, if there is one. See [`WebexEventStream::health()`].
+    #[must_use]
+    pub fn health(&self) -> Option<StreamHealth> {
+        self.stream.as_ref().map(WebexEventStream::health)
+    }
+
+    /// Get the next event, reconnecting first if the connection has been lost.
+    ///
+    /// # Errors
+    /// Errors that don't close the connection (such as an event that fails to deserialize) are
+    /// passed through, and the stream keeps working. Errors that close it trigger a reconnect
+    /// instead; only if all reconnection attempts fail, or one fails with an error that retrying
+    /// won't fix (see [`Error::is_retryable()`]), is that connection error returned.
+    /// [`is_connected()`](Self::is_connected) tells the two apart: it is still true after an
+    /// error that was passed through.
+    pub async fn next(&mut self) -> Result<StreamEvent, Error> {
+        loop {
+            let Some(stream) = self.stream.as_mut() else {
+                return self.reconnect().await;
+            };
+            match stream.next().await {
+                Ok(event) => return Ok(StreamEvent::Event(Box::new(event))),
+                Err(e) if stream.is_open => return Err(e),
+                Err(e) => {
+                    warn!("Event stream closed ({e}), reconnecting");
+                    self.stream = None;
+                    self.disconnected_at = Some(Utc::now());
+                }
+            }
+        }
+    }
+
+    async fn reconnect(&mut self) -> Result<StreamEvent, Error> {
+        let disconnected_at = *self.disconnected_at.get_or_insert_with(Utc::now);
+        let mut attempt = 1;
+        loop {
+            match self.webex.event_stream().await {
+                Ok(stream) => {
+                    debug!("Reconnected after {attempt} attempt(s)");
+                    self.stream = Some(stream);
+                    self.disconnected_at = None;
+                    return Ok(StreamEvent::Reconnected {
+                        disconnected_at,
+                        attempts: attempt,
+                    });
+                }
+                Err(e) if !e.is_retryable() || attempt >= self.retry_policy.max_attempts => {
+                    return Err(e)
+                }
+                Err(e) => {
+                    let delay = self.retry_policy.backoff(attempt);
+                    warn!("Reconnection attempt {attempt} failed ({e}), retrying in {delay:?}");
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{devices_reply, event_message, mock_server, mock_websocket};
+    use tokio_tungstenite::tungstenite::Message as TMessage;
+
+    #[tokio::test]
+    async fn reconnects_after_close() {
+        let ws_url = mock_websocket(
+            vec![
+                vec![event_message("status.start_typing"), TMessage::Close(None)],
+                vec![event_message("conversation.highlight")],
+            ],
+            true,
+        )
+        .await;
+        let (wdm_url, requests) = mock_server(vec![
+            ("200 OK", "", devices_reply(&ws_url)),
+            ("200 OK", "", devices_reply(&ws_url)),
+        ])
+        .await;
+        let webex = Webex::builder("token")
+            .wdm_url(wdm_url)
+            .build()
+            .await
+            .unwrap();
+        let mut stream = webex.reconnecting_event_stream().await.unwrap();
+
+        let StreamEvent::Event(event) = stream.next().await.unwrap() else {
+            panic!("expected an event");
+        };
+        assert_eq!(event.data.event_type, "status.start_typing");
+        assert!(matches!(
+            stream.next().await.unwrap(),
+            StreamEvent::Reconnected { attempts: 1, .. }
+        ));
+        assert!(stream.is_connected());
+        let StreamEvent::Event(event) = stream.next().await.unwrap() else {
+            panic!("expected an event");
+        };
+        assert_eq!(event.data.event_type, "conversation.highlight");
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn gives_up_when_token_is_rejected() {
+        let ws_url = mock_websocket(
+            vec![vec![
+                event_message("status.start_typing"),
+                TMessage::Close(None),
+            ]],
+            true,
+        )
+        .await;
+        let (wdm_url, requests) = mock_server(vec![
+            ("200 OK", "", devices_reply(&ws_url)),
+            (
+                "401 Unauthorized",
+                "",
+                r#"{"message":"The request requires a valid access token set in the Authorization request header."}"#.to_string(),
+            ),
+        ])
+        .await;
+        let webex = Webex::builder("token")
+            .wdm_url(wdm_url)
+            .build()
+            .await
+            .unwrap();
+        let mut stream = webex.reconnecting_event_stream().await.unwrap();
+
+        assert!(matches!(stream.next().await, Ok(StreamEvent::Event(_))));
+        let err = tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .expect("should not keep retrying")
+            .unwrap_err();
+        assert!(matches!(err, Error::Authentication(_)), "{err:?}");
+        assert!(!stream.is_connected());
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,223 @@
+#![deny(missing_docs)]
+//! Retry behaviour for REST requests that are throttled or fail transiently
+
+use crate::error::Error;
+use reqwest::{Method, StatusCode};
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+/// Controls how REST requests are retried when the API throttles the client (HTTP 423/429), when
+/// the server has a transient failure (HTTP 5xx), or when the connection fails.
+///
+/// Throttled requests and requests that never reached the server are always safe to retry.
+/// Requests that failed with a 5xx or timed out may already have been processed, so they are only
+/// retried for idempotent methods (GET, HEAD, PUT, DELETE, OPTIONS) unless
+/// [`retry_non_idempotent`](Self::retry_non_idempotent) is set.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first one. `1` disables retries.
+    pub max_attempts: u32,
+    /// Delay before the first retry. Doubles with each further attempt.
+    pub initial_backoff: Duration,
+    /// Upper bound for the computed backoff. A `Retry-After` sent by the server is honored up to
+    /// [`max_retry_after`](Self::max_retry_after) instead.
+    pub max_backoff: Duration,
+    /// Upper bound for a `Retry-After` sent by the server, so that a misbehaving server can't stall
+    /// a request indefinitely.
+    pub max_retry_after: Duration,
+    /// Randomize the backoff (between half and all of the computed delay) so that many clients
+    /// don't retry in lockstep.
+    pub jitter: bool,
+    /// Also retry POST (and other non-idempotent) requests after a 5xx or a timeout.
+    pub retry_non_idempotent: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            max_retry_after: Duration::from_secs(5 * 60),
+            jitter: true,
+            retry_non_idempotent: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    #[must_use]
+    pub fn disabled() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// How long to wait before retrying after `error`, or `None` if the request should not be
+    /// retried. `attempt` is the number of the attempt that just failed, starting at 1.
+    pub(crate) fn retry_delay(
+        &self,
+        error: &Error,
+        attempt: u32,
+        method: &Method,
+    ) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        let may_repeat = self.retry_non_idempotent || is_idempotent(method);
+        match error {
+            Error::Throttled(e) => Some(e.retry_after.map_or_else(
+                || self.backoff(attempt),
+                |delay| delay.min(self.max_retry_after),
+            )),
+            Error::Api(e) if may_repeat && is_transient(e.status) => Some(self.backoff(attempt)),
+            Error::Timeout(_) if may_repeat => Some(self.backoff(attempt)),
+            Error::Reqwest(e) if e.is_connect() => Some(self.backoff(attempt)),
+            _ => None,
+        }
+    }
+
+    /// Exponential backoff for the given attempt (starting at 1), without regard to the error.
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        let delay = self
+            .initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff);
+        if self.jitter {
+            let half = delay / 2;
+            let nanos = u64::try_from(half.as_nanos()).unwrap_or(u64::MAX);
+            half + Duration::from_nanos(random_u64() % nanos.saturating_add(1))
+        } else {
+            delay
+        }
+    }
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    [
+        Method::GET,
+        Method::HEAD,
+        Method::PUT,
+        Method::DELETE,
+        Method::OPTIONS,
+    ]
+    .contains(method)
+}
+
+/// 5xx statuses that usually go away by themselves.
+pub(crate) const fn is_transient(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+// Good enough randomness for jitter without pulling in a RNG crate: every `RandomState` is seeded
+// with fresh random keys.
+fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ApiError;
+
+    fn status(status: StatusCode) -> Error {
+        ApiError::new(status).into()
+    }
+
+    fn no_jitter() -> RetryPolicy {
+        RetryPolicy {
+            jitter: false,
+            ..RetryPolicy::default()
+        }
+    }
+
+    #[test]
+    fn honors_retry_after() {
+        let policy = no_jitter();
+        let err = Error::from(ApiError {
+            retry_after: Some(Duration::from_secs(7)),
+            ..ApiError::new(StatusCode::TOO_MANY_REQUESTS)
+        });
+        assert_eq!(
+            policy.retry_delay(&err, 1, &Method::POST),
+            Some(Duration::from_secs(7))
+        );
+    }
+
+    #[test]
+    fn caps_retry_after() {
+        let policy = no_jitter();
+        let err = Error::from(ApiError {
+            retry_after: Some(Duration::from_secs(24 * 60 * 60)),
+            ..ApiError::new(StatusCode::TOO_MANY_REQUESTS)
+        });
+        assert_eq!(
+            policy.retry_delay(&err, 1, &Method::GET),
+            Some(policy.max_retry_after)
+        );
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            max_backoff: Duration::from_secs(2),
+            ..no_jitter()
+        };
+        let err = status(StatusCode::SERVICE_UNAVAILABLE);
+        let delays: Vec<_> = (1..=4)
+            .map(|attempt| policy.retry_delay(&err, attempt, &Method::GET))
+            .collect();
+        assert_eq!(
+            delays,
+            [500, 1000, 2000, 2000].map(|ms| Some(Duration::from_millis(ms)))
+        );
+    }
+
+    #[test]
+    fn jitter_stays_in_range() {
+        let policy = RetryPolicy::default();
+        for _ in 0..100 {
+            let delay = policy.backoff(2);
+            assert!(delay >= Duration::from_millis(500) && delay <= Duration::from_secs(1));
+        }
+    }
+
+    #[test]
+    fn non_idempotent_not_retried_on_server_error() {
+        let err = status(StatusCode::BAD_GATEWAY);
+        assert_eq!(no_jitter().retry_delay(&err, 1, &Method::POST), None);
+        let policy = RetryPolicy {
+            retry_non_idempotent: true,
+            ..no_jitter()
+        };
+        assert!(policy.retry_delay(&err, 1, &Method::POST).is_some());
+    }
+
+    #[test]
+    fn stops_after_max_attempts() {
+        let err = status(StatusCode::TOO_MANY_REQUESTS);
+        assert!(no_jitter().retry_delay(&err, 3, &Method::GET).is_some());
+        assert_eq!(no_jitter().retry_delay(&err, 4, &Method::GET), None);
+        assert_eq!(
+            RetryPolicy::disabled().retry_delay(&err, 1, &Method::GET),
+            None
+        );
+        assert_eq!(
+            no_jitter().retry_delay(&status(StatusCode::NOT_FOUND), 1, &Method::GET),
+            None
+        );
+    }
+}
@@ -0,0 +1,1391 @@
+#![deny(missing_docs)]
+//! An offline stand-in for the Webex API, to test bots built on [`Webex`] without a network
+//!
+//! [`MockWebex`] is a local HTTP and websocket server that keeps people, rooms, messages,
+//! attachment actions and devices in memory. It serves the REST endpoints used by this crate, the
+//! U2C service catalog and device registration, and answers the mercury authorization handshake
+//! of [`Webex::event_stream()`]. Tests act as other users through methods such as
+//! [`post_message()`](MockWebex::post_message), which store the resource and send the matching
+//! event to the connected event stream, and check what the client did with
+//! [`requests()`](MockWebex::requests), [`assert_requested()`](MockWebex::assert_requested)
+//! and [`next_sent_message()`](MockWebex::next_sent_message).
+//!
+//! ```
+//! use webex::{
+//!     bot::{Bot, Context, Router},
+//!     testing::MockWebex,
+//!     RoomType,
+//! };
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), webex::error::Error> {
+//! let mock = MockWebex::start().await;
+//! let alice = mock.add_person("Alice", "alice@example.com");
+//! let room = mock.add_room("Test room", RoomType::Group);
+//!
+//! let router = Router::new().command("echo", "", |ctx: Context| async move {
+//!     ctx.reply(&ctx.args.join(" ")).await?;
+//!     Ok(())
+//! });
+//! tokio::spawn(Bot::new(mock.client().await?, router).run());
+//!
+//! mock.post_message(&alice, &room, "echo hello");
+//! assert_eq!(mock.next_sent_message().await.markdown.as_deref(), Some("hello"));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    error::Error, Activity, ActivityParent, Actor, AlertType, AttachmentAction, DeviceData, Event,
+    EventData, GlobalId, GlobalIdType, Message, MessageOut, Object, Person, PersonType,
+    PresenceStatus, Room, RoomType, Target, Webex, WebexBuilder,
+};
+use base64::Engine;
+use chrono::Utc;
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, trace, warn};
+use reqwest::StatusCode;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
+use tokio_tungstenite::{
+    tungstenite::{
+        handshake::derive_accept_key,
+        protocol::{frame::coding::CloseCode, CloseFrame, Role},
+        Message as TMessage,
+    },
+    WebSocketStream,
+};
+use uuid::Uuid;
+
+/// Token accepted by a server created with [`MockWebex::start()`].
+pub const MOCK_TOKEN: &str = "mock-token";
+
+/// A request received by a [`MockWebex`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecordedRequest {
+    /// HTTP method, such as `GET`.
+    pub method: String,
+    /// Path without the query string, such as `/messages`.
+    pub path: String,
+    /// Decoded query parameters, in order.
+    pub query: Vec<(String, String)>,
+    /// Request body, lossily decoded as UTF-8.
+    pub body: String,
+}
+
+impl RecordedRequest {
+    /// The first query parameter with this name.
+    #[must_use]
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The body parsed as JSON, if it is JSON.
+    #[must_use]
+    pub fn json(&self) -> Option<Value> {
+        serde_json::from_str(&self.body).ok()
+    }
+
+    fn matches(&self, method: &str, path: &str) -> bool {
+        self.method.eq_ignore_ascii_case(method) && self.path == path
+    }
+}
+
+/// What the websocket task of a [`MockWebex`] is asked to do.
+enum Injected {
+    Frame(TMessage),
+    Close,
+}
+
+struct State {
+    url: String,
+    me: Person,
+    people: Vec<Person>,
+    rooms: Vec<Room>,
+    /// Direct room with each person, by person ID.
+    direct_rooms: HashMap<String, String>,
+    messages: Vec<Message>,
+    actions: Vec<AttachmentAction>,
+    devices: Vec<DeviceData>,
+    requests: Vec<RecordedRequest>,
+    /// Messages posted through the API, and how many of them were returned by
+    /// [`MockWebex::next_sent_message()`].
+    sent: Vec<Message>,
+    sent_taken: usize,
+    failures: Vec<(String, String, StatusCode)>,
+    connections: usize,
+    sequence: i64,
+    events: mpsc::UnboundedSender<Injected>,
+}
+
+struct Shared {
+    token: String,
+    url: String,
+    state: Mutex<State>,
+    /// Bumped after each request, to wake up [`MockWebex::next_sent_message()`].
+    changed: watch::Sender<usize>,
+    /// Only one event stream receives the injected events at a time; the next one picks up
+    /// where it left off.
+    events: Arc<tokio::sync::Mutex<mpsc::UnboundedReceiver<Injected>>>,
+}
+
+impl Shared {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A local server emulating the parts of the Webex API used by this crate. See the
+/// [module documentation](self).
+///
+/// The server runs until the `MockWebex` is dropped. IDs are base64 geo-IDs like those of the
+/// real API, and events carry UUIDs, so that [`WebexEvent`](crate::WebexEvent) conversion and
+/// [`Webex::enrich_event()`] work as they do against Webex. Like the real service, messages
+/// posted through the API are also sent to the event stream.
+pub struct MockWebex {
+    shared: Arc<Shared>,
+    server: JoinHandle<()>,
+}
+
+impl MockWebex {
+    /// Start a server accepting [`MOCK_TOKEN`], whose token belongs to a bot named "Test Bot".
+    ///
+    /// # Panics
+    /// If no local port can be bound.
+    pub async fn start() -> Self {
+        Self::start_with_token(MOCK_TOKEN).await
+    }
+
+    /// Start a server accepting the given token.
+    ///
+    /// # Panics
+    /// If no local port can be bound.
+    pub async fn start_with_token(token: &str) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind the mock Webex server");
+        let url = format!(
+            "http://{}",
+            listener
+                .local_addr()
+                .expect("Bound socket should have an address")
+        );
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+        let org_id = base64::engine::general_purpose::STANDARD
+            .encode(format!("ciscospark://us/ORGANIZATION/{}", Uuid::new_v4()));
+        let me = Person {
+            id: new_id(GlobalIdType::Person),
+            emails: vec!["test-bot@webex.bot".to_string()],
+            display_name: "Test Bot".to_string(),
+            nick_name: "Test".to_string(),
+            org_id,
+            created: Some(Utc::now()),
+            status: PresenceStatus::Active,
+            person_type: PersonType::Bot,
+            ..Person::default()
+        };
+        let state = State {
+            url: url.clone(),
+            people: vec![me.clone()],
+            me,
+            rooms: Vec::new(),
+            direct_rooms: HashMap::new(),
+            messages: Vec::new(),
+            actions: Vec::new(),
+            devices: Vec::new(),
+            requests: Vec::new(),
+            sent: Vec::new(),
+            sent_taken: 0,
+            failures: Vec::new(),
+            connections: 0,
+            sequence: 0,
+            events: events_tx,
+        };
+        let shared = Arc::new(Shared {
+            token: token.to_string(),
+            url,
+            state: Mutex::new(state),
+            changed: watch::Sender::new(0),
+            events: Arc::new(tokio::sync::Mutex::new(events_rx)),
+        });
+        let server = tokio::spawn(serve(listener, shared.clone()));
+        Self { shared, server }
+    }
+
+    /// Base URL of the server, to use as REST, U2C and WDM URL.
+    #[must_use]
+    pub fn url(&self) -> &str {
+        &self.shared.url
+    }
+
+    /// A builder for a client of this server, to change further settings before building it.
+    /// The WDM URL is left to be discovered through the service catalog.
+    pub fn client_builder(&self) -> WebexBuilder {
+        Webex::builder(&self.shared.token)
+            .rest_url(self.url())
+            .u2c_url(self.url())
+    }
+
+    /// A client of this server.
+    ///
+    /// # Errors
+    /// As for [`WebexBuilder::build()`].
+    pub async fn client(&self) -> Result<Webex, Error> {
+        self.client_builder().build().await
+    }
+
+    /// The person the token belongs to.
+    #[must_use]
+    pub fn me(&self) -> Person {
+        self.shared.state().me.clone()
+    }
+
+    /// Add a person, to act as the author of messages and card submissions.
+    #[must_use]
+    pub fn add_person(&self, display_name: &str, email: &str) -> Person {
+        let mut state = self.shared.state();
+        let person = Person {
+            id: new_id(GlobalIdType::Person),
+            emails: vec![email.to_string()],
+            display_name: display_name.to_string(),
+            org_id: state.me.org_id.clone(),
+            created: Some(Utc::now()),
+            status: PresenceStatus::Active,
+            person_type: PersonType::Person,
+            ..Person::default()
+        };
+        state.people.push(person.clone());
+        person
+    }
+
+    /// Add a room that the bot is a member of. Direct rooms are better created with
+    /// [`direct_room()`](Self::direct_room).
+    #[must_use]
+    pub fn add_room(&self, title: &str, room_type: RoomType) -> Room {
+        let mut state = self.shared.state();
+        let creator_id = state.me.id.clone();
+        state.create_room(Some(title.to_string()), room_type, creator_id)
+    }
+
+    /// The direct room between the bot and a person, created if needed.
+    #[must_use]
+    pub fn direct_room(&self, person: &Person) -> Room {
+        self.shared.state().direct_room(person)
+    }
+
+    /// Post a message as `from`, and send the event for it.
+    #[allow(clippy::must_use_candidate)]
+    pub fn post_message(&self, from: &Person, room: &Room, text: &str) -> Message {
+        self.shared.state().post_message(
+            from,
+            MessageOut {
+                room_id: Some(room.id.clone()),
+                text: Some(text.to_string()),
+                ..MessageOut::default()
+            },
+        )
+    }
+
+    /// Reply to a message as `from`, in the thread of `parent`, and send the event for it.
+    #[allow(clippy::must_use_candidate)]
+    pub fn post_reply(&self, from: &Person, parent: &Message, text: &str) -> Message {
+        self.shared.state().post_message(
+            from,
+            MessageOut {
+                room_id: parent.room_id.clone(),
+                parent_id: parent.parent_id.clone().or_else(|| parent.id.clone()),
+                text: Some(text.to_string()),
+                ..MessageOut::default()
+            },
+        )
+    }
+
+    /// Submit the card of a message as `from`, and send the event for it.
+    #[allow(clippy::must_use_candidate)]
+    pub fn submit_card(
+        &self,
+        from: &Person,
+        message: &Message,
+        inputs: HashMap<String, Value>,
+    ) -> AttachmentAction {
+        let mut state = self.shared.state();
+        let (uuid, id) = new_uuid_id(GlobalIdType::AttachmentAction);
+        let action = AttachmentAction {
+            id,
+            action_type: Some("submit".to_string()),
+            message_id: message.id.clone(),
+            inputs: Some(inputs),
+            person_id: Some(from.id.clone()),
+            room_id: message.room_id.clone(),
+            created: Some(Utc::now()),
+        };
+        state.actions.push(action.clone());
+        let target = action
+            .room_id
+            .as_deref()
+            .and_then(|room_id| state.room(room_id))
+            .map(|room| state.target(&room));
+        let object = Object {
+            object_type: "submit".to_string(),
+            inputs: serde_json::to_string(&action.inputs).ok(),
+            ..Object::default()
+        };
+        let event = state.activity_event("cardAction", from, uuid, object, target);
+        state.emit(&event);
+        action
+    }
+
+    /// Add the bot to a room as `by`, and send the event for it.
+    pub fn add_bot_to_room(&self, by: &Person, room: &Room) {
+        self.shared.state().membership_event("add", by, room);
+    }
+
+    /// Remove the bot from a room as `by`, and send the event for it.
+    pub fn remove_bot_from_room(&self, by: &Person, room: &Room) {
+        self.shared.state().membership_event("leave", by, room);
+    }
+
+    /// Send an event to the event stream. Events sent while no stream is connected are
+    /// delivered once one connects.
+    pub fn send_event(&self, event: &Event) {
+        self.shared.state().emit(event);
+    }
+
+    /// Send a raw text frame to the event stream, e.g. to test how a client copes with
+    /// unexpected messages.
+    pub fn send_text_frame(&self, text: &str) {
+        let frame = TMessage::Text(text.to_string());
+        let _ = self.shared.state().events.send(Injected::Frame(frame));
+    }
+
+    /// Send a raw binary frame to the event stream. Events are sent as JSON in binary frames, so
+    /// this can be used to send malformed ones.
+    pub fn send_binary_frame(&self, data: Vec<u8>) {
+        let _ = self
+            .shared
+            .state()
+            .events
+            .send(Injected::Frame(TMessage::Binary(data)));
+    }
+
+    /// Close the connected event stream, e.g. to test reconnecting. The stream connected next
+    /// receives the events sent after this.
+    pub fn close_event_stream(&self) {
+        let _ = self.shared.state().events.send(Injected::Close);
+    }
+
+    /// Number of event streams that connected and authenticated so far.
+    #[must_use]
+    pub fn event_stream_connections(&self) -> usize {
+        self.shared.state().connections
+    }
+
+    /// Answer the next request matching `method` and `path` (without query string) with an
+    /// error status, e.g. to test retries or error handling.
+    pub fn fail_next(&self, method: &str, path: &str, status: StatusCode) {
+        self.shared
+            .state()
+            .failures
+            .push((method.to_string(), path.to_string(), status));
+    }
+
+    /// All requests received so far, in order. Websocket upgrades are not included.
+    #[must_use]
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.shared.state().requests.clone()
+    }
+
+    /// Requests received so far with this method and path (without query string).
+    #[must_use]
+    pub fn requests_to(&self, method: &str, path: &str) -> Vec<RecordedRequest> {
+        self.shared
+            .state()
+            .requests
+            .iter()
+            .filter(|request| request.matches(method, path))
+            .cloned()
+            .collect()
+    }
+
+    /// The last request with this method and path (without query string).
+    ///
+    /// # Panics
+    /// If there was no such request, listing the requests that were received.
+    #[allow(clippy::must_use_candidate)]
+    pub fn assert_requested(&self, method: &str, path: &str) -> RecordedRequest {
+        let state = self.shared.state();
+        let found = state
+            .requests
+            .iter()
+            .rev()
+            .find(|request| request.matches(method, path))
+            .cloned();
+        let received: Vec<_> = state
+            .requests
+            .iter()
+            .map(|request| format!("{} {}", request.method, request.path))
+            .collect();
+        drop(state);
+        found.unwrap_or_else(|| {
+            panic!("Expected a {method} {path} request, received: {received:#?}")
+        })
+    }
+
+    /// All messages, including those posted by tests, oldest first.
+    #[must_use]
+    pub fn messages(&self) -> Vec<Message> {
+        self.shared.state().messages.clone()
+    }
+
+    /// The next message posted through the API, waiting for one if needed. Each message is
+    /// returned once. Wrap it in [`tokio::time::timeout()`] to bound the wait.
+    pub async fn next_sent_message(&self) -> Message {
+        let mut changed = self.shared.changed.subscribe();
+        loop {
+            let next = {
+                let mut state = self.shared.state();
+                let next = state.sent.get(state.sent_taken).cloned();
+                if next.is_some() {
+                    state.sent_taken += 1;
+                }
+                next
+            };
+            if let Some(message) = next {
+                return message;
+            }
+            // The sender is kept alive by `self`, so this only fails if the server is gone
+            if changed.changed().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+impl Drop for MockWebex {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+impl std::fmt::Debug for MockWebex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockWebex")
+            .field("url", &self.shared.url)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A geo-ID for a new resource, as returned by the API.
+fn new_id(type_: GlobalIdType) -> String {
+    new_uuid_id(type_).1
+}
+
+/// A UUID, as sent in events, and the matching geo-ID.
+fn new_uuid_id(type_: GlobalIdType) -> (String, String) {
+    let uuid = Uuid::new_v4().to_string();
+    let id = GlobalId::new_with_cluster_unchecked(type_, uuid.clone(), None)
+        .id()
+        .to_string();
+    (uuid, id)
+}
+
+/// The UUID inside a geo-ID.
+fn uuid_of(id: &str) -> String {
+    base64::engine::general_purpose::STANDARD
+        .decode(id)
+        .ok()
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .and_then(|decoded| decoded.rsplit('/').next().map(str::to_string))
+        .unwrap_or_else(|| id.to_string())
+}
+
+fn to_json(value: &impl Serialize) -> Value {
+    serde_json::to_value(value).unwrap_or_default()
+}
+
+fn items<'a, T: Serialize + 'a>(items: impl IntoIterator<Item = &'a T>) -> Value {
+    json!({ "items": items.into_iter().map(to_json).collect::<Vec<_>>() })
+}
+
+type Reply = (StatusCode, Option<Value>);
+
+fn error_reply(status: StatusCode, message: &str) -> Reply {
+    (
+        status,
+        Some(json!({
+            "message": message,
+            "errors": [{ "description": message }],
+            "trackingId": format!("MOCK_{}", Uuid::new_v4()),
+        })),
+    )
+}
+
+fn not_found() -> Reply {
+    error_reply(
+        StatusCode::NOT_FOUND,
+        "The requested resource could not be found.",
+    )
+}
+
+impl State {
+    fn person(&self, id: &str) -> Option<Person> {
+        self.people.iter().find(|person| person.id == id).cloned()
+    }
+
+    fn room(&self, id: &str) -> Option<Room> {
+        self.rooms.iter().find(|room| room.id == id).cloned()
+    }
+
+    fn create_room(
+        &mut self,
+        title: Option<String>,
+        room_type: RoomType,
+        creator_id: String,
+    ) -> Room {
+        let now = Utc::now();
+        let room = Room {
+            id: new_id(GlobalIdType::Room),
+            title,
+            room_type,
+            is_locked: false,
+            team_id: None,
+            last_activity: Some(now),
+            creator_id,
+            created: Some(now),
+        };
+        self.rooms.push(room.clone());
+        room
+    }
+
+    fn direct_room(&mut self, person: &Person) -> Room {
+        if let Some(room) = self
+            .direct_rooms
+            .get(&person.id)
+            .and_then(|room_id| self.room(room_id))
+        {
+            return room;
+        }
+        let room = self.create_room(
+            Some(person.display_name.clone()),
+            RoomType::Direct,
+            person.id.clone(),
+        );
+        self.direct_rooms.insert(person.id.clone(), room.id.clone());
+        room
+    }
+
+    fn emit(&self, event: &Event) {
+        trace!("Queueing event {}", event.id);
+        match serde_json::to_vec(event) {
+            Ok(bytes) => {
+                let _ = self.events.send(Injected::Frame(TMessage::Binary(bytes)));
+            }
+            Err(e) => warn!("Failed to serialize event: {e}"),
+        }
+    }
+
+    fn actor(person: &Person) -> Actor {
+        Actor {
+            id: uuid_of(&person.id),
+            object_type: "person".to_string(),
+            display_name: Some(person.display_name.clone()),
+            org_id: Some(uuid_of(&person.org_id)),
+            email_address: person.emails.first().cloned(),
+            entry_uuid: uuid_of(&person.id),
+            actor_type: Some(
+                match person.person_type {
+                    PersonType::Bot => "ROBOT",
+                    _ => "PERSON",
+                }
+                .to_string(),
+            ),
+        }
+    }
+
+    fn target(&self, room: &Room) -> Target {
+        let uuid = uuid_of(&room.id);
+        Target {
+            url: format!("{}/conversations/{uuid}", self.url),
+            id: uuid,
+            object_type: "conversation".to_string(),
+            participants: None,
+            activities: None,
+            tags: Vec::new(),
+            global_id: Some(room.id.clone()),
+        }
+    }
+
+    fn activity_event(
+        &mut self,
+        verb: &str,
+        actor: &Person,
+        id: String,
+        object: Object,
+        target: Option<Target>,
+    ) -> Event {
+        self.sequence += 1;
+        let now = Utc::now();
+        let actor = Self::actor(actor);
+        Event {
+            id: Uuid::new_v4().to_string(),
+            data: EventData {
+                event_type: "conversation.activity".to_string(),
+                actor: Some(actor.clone()),
+                conversation_id: target.as_ref().map(|target| target.id.clone()),
+                activity: Some(Activity {
+                    actor,
+                    id,
+                    object_type: "activity".to_string(),
+                    object,
+                    published: now.to_rfc3339(),
+                    target,
+                    verb: verb.to_string(),
+                    ..Activity::default()
+                }),
+            },
+            timestamp: now.timestamp_millis(),
+            tracking_id: format!("MOCK_{}", self.sequence),
+            alert_type: Some(AlertType::Full),
+            headers: HashMap::new(),
+            sequence_number: self.sequence,
+            filter_message: false,
+        }
+    }
+
+    fn membership_event(&mut self, verb: &str, by: &Person, room: &Room) {
+        let me = self.me.clone();
+        let object = Object {
+            id: Some(uuid_of(&me.id)),
+            object_type: "person".to_string(),
+            display_name: Some(me.display_name),
+            ..Object::default()
+        };
+        let target = self.target(room);
+        let event = self.activity_event(verb, by, Uuid::new_v4().to_string(), object, Some(target));
+        self.emit(&event);
+    }
+
+    /// Store a message by `from`, and send the event for it.
+    fn post_message(&mut self, from: &Person, message: MessageOut) -> Message {
+        let room = message
+            .room_id
+            .as_deref()
+            .and_then(|room_id| self.room(room_id));
+        let (uuid, id) = new_uuid_id(GlobalIdType::Message);
+        let now = Utc::now();
+        let message = Message {
+            id: Some(id),
+            room_id: room.as_ref().map(|room| room.id.clone()),
+            room_type: room.as_ref().map(|room| room.room_type.clone()),
+            to_person_id: message.to_person_id,
+            to_person_email: message.to_person_email,
+            text: message.text.or_else(|| message.markdown.clone()),
+            markdown: message.markdown,
+            files: message.files,
+            person_id: Some(from.id.clone()),
+            person_email: from.emails.first().cloned(),
+            attachments: message.attachments,
+            created: Some(now),
+            parent_id: message.parent_id,
+            ..Message::default()
+        };
+        self.messages.push(message.clone());
+        if let Some(room) = self
+            .rooms
+            .iter_mut()
+            .find(|r| Some(&r.id) == message.room_id.as_ref())
+        {
+            room.last_activity = Some(now);
+        }
+        let object = Object {
+            object_type: "comment".to_string(),
+            display_name: message.text.clone(),
+            content: message.markdown.clone(),
+            ..Object::default()
+        };
+        let target = room.as_ref().map(|room| self.target(room));
+        let mut event = self.activity_event("post", from, uuid, object, target);
+        if let (Some(parent_id), Some(activity)) = (&message.parent_id, &mut event.data.activity) {
+            activity.parent = Some(ActivityParent {
+                actor_id: activity.actor.id.clone(),
+                id: uuid_of(parent_id),
+                published: Some(now),
+                parent_type: "reply".to_string(),
+            });
+        }
+        self.emit(&event);
+        message
+    }
+
+    fn handle(&mut self, request: &RecordedRequest) -> Reply {
+        if let Some(index) = self
+            .failures
+            .iter()
+            .position(|(method, path, _)| request.matches(method, path))
+        {
+            let (_, _, status) = self.failures.remove(index);
+            return error_reply(status, status.canonical_reason().unwrap_or("Failed"));
+        }
+        let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["people", "me"]) => (StatusCode::OK, Some(to_json(&self.me))),
+            ("GET", ["people"]) => self.list_people(request),
+            ("GET", ["people", id]) => self
+                .person(id)
+                .map_or_else(not_found, |person| (StatusCode::OK, Some(to_json(&person)))),
+            ("GET", ["rooms"]) => self.list_rooms(request),
+            ("POST", ["rooms"]) => self.create_room_request(request),
+            ("GET", ["rooms", id]) => self
+                .room(id)
+                .map_or_else(not_found, |room| (StatusCode::OK, Some(to_json(&room)))),
+            ("PUT", ["rooms", id]) => self.update_room(id, request),
+            ("DELETE", ["rooms", id]) => {
+                let before = self.rooms.len();
+                self.rooms.retain(|room| room.id != *id);
+                if self.rooms.len() == before {
+                    not_found()
+                } else {
+                    (StatusCode::NO_CONTENT, None)
+                }
+            }
+            ("GET", ["messages"]) => self.list_messages(request),
+            ("POST", ["messages"]) => self.send_message(request),
+            ("GET", ["messages", id]) => self
+                .messages
+                .iter()
+                .find(|message| message.id.as_deref() == Some(*id))
+                .map_or_else(not_found, |message| {
+                    (StatusCode::OK, Some(to_json(message)))
+                }),
+            ("PUT", ["messages", id]) => self.edit_message(id, request),
+            ("DELETE", ["messages", id]) => {
+                let before = self.messages.len();
+                self.messages
+                    .retain(|message| message.id.as_deref() != Some(*id));
+                if self.messages.len() == before {
+                    not_found()
+                } else {
+                    (StatusCode::NO_CONTENT, None)
+                }
+            }
+            ("GET", ["attachment", "actions", id]) => self
+                .actions
+                .iter()
+                .find(|action| action.id == *id)
+                .map_or_else(not_found, |action| (StatusCode::OK, Some(to_json(action)))),
+            ("POST", ["attachment", "actions"]) => self.create_action(request),
+            ("GET", ["devices"]) => (
+                StatusCode::OK,
+                Some(json!({ "devices": self.devices.iter().map(to_json).collect::<Vec<_>>() })),
+            ),
+            ("POST", ["devices"]) => self.register_device(request),
+            ("GET", ["limited", "catalog"]) => (
+                StatusCode::OK,
+                Some(json!({ "serviceLinks": { "wdm": self.url }, "format": "hostmap" })),
+            ),
+            _ => not_found(),
+        }
+    }
+
+    fn list_people(&self, request: &RecordedRequest) -> Reply {
+        let email = request.query_param("email");
+        let display_name = request.query_param("displayName").map(str::to_lowercase);
+        let ids: Option<Vec<&str>> = request
+            .query_param("id")
+            .map(|ids| ids.split(',').collect());
+        if email.is_none() && display_name.is_none() && ids.is_none() {
+            return error_reply(
+                StatusCode::BAD_REQUEST,
+                "Email, displayName, or id list should be specified.",
+            );
+        }
+        let people = self.people.iter().filter(|person| {
+            email.is_none_or(|email| {
+                person
+                    .emails
+                    .iter()
+                    .any(|address| address.eq_ignore_ascii_case(email))
+            }) && display_name.as_ref().is_none_or(|name| {
+                person
+                    .display_name
+                    .to_lowercase()
+                    .starts_with(name.as_str())
+            }) && ids
+                .as_ref()
+                .is_none_or(|ids| ids.contains(&person.id.as_str()))
+        });
+        (StatusCode::OK, Some(items(people)))
+    }
+
+    fn list_rooms(&self, request: &RecordedRequest) -> Reply {
+        let room_type = request.query_param("type");
+        let team_id = request.query_param("teamId");
+        let rooms = self.rooms.iter().filter(|room| {
+            room_type.is_none_or(|room_type| to_json(&room.room_type) == room_type)
+                && team_id.is_none_or(|team_id| room.team_id.as_deref() == Some(team_id))
+        });
+        (StatusCode::OK, Some(items(max(request, rooms))))
+    }
+
+    fn create_room_request(&mut self, request: &RecordedRequest) -> Reply {
+        let Some(body) = request.json() else {
+            return error_reply(StatusCode::BAD_REQUEST, "Invalid JSON");
+        };
+        let Some(title) = body["title"].as_str() else {
+            return error_reply(StatusCode::BAD_REQUEST, "title is required");
+        };
+        let creator_id = self.me.id.clone();
+        let mut room = self.create_room(Some(title.to_string()), RoomType::Group, creator_id);
+        if let Some(stored) = self.rooms.iter_mut().find(|r| r.id == room.id) {
+            stored.team_id = body["teamId"].as_str().map(str::to_string);
+            stored.is_locked = body["isLocked"].as_bool().unwrap_or_default();
+            room = stored.clone();
+        }
+        (StatusCode::OK, Some(to_json(&room)))
+    }
+
+    fn update_room(&mut self, id: &str, request: &RecordedRequest) -> Reply {
+        let body = request.json().unwrap_or_default();
+        let Some(room) = self.rooms.iter_mut().find(|room| room.id == id) else {
+            return not_found();
+        };
+        if let Some(title) = body["title"].as_str() {
+            room.title = Some(title.to_string());
+        }
+        if let Some(team_id) = body["teamId"].as_str() {
+            room.team_id = Some(team_id.to_string());
+        }
+        if let Some(is_locked) = body["isLocked"].as_bool() {
+            room.is_locked = is_locked;
+        }
+        (StatusCode::OK, Some(to_json(room)))
+    }
+
+    fn list_messages(&self, request: &RecordedRequest) -> Reply {
+        let Some(room_id) = request.query_param("roomId") else {
+            return error_reply(StatusCode::BAD_REQUEST, "roomId is required");
+        };
+        let parent_id = request.query_param("parentId");
+        // Newest first, like the API
+        let messages = self.messages.iter().rev().filter(|message| {
+            message.room_id.as_deref() == Some(room_id)
+                && parent_id.is_none_or(|parent_id| message.parent_id.as_deref() == Some(parent_id))
+        });
+        (StatusCode::OK, Some(items(max(request, messages))))
+    }
+
+    fn send_message(&mut self, request: &RecordedRequest) -> Reply {
+        let Some(mut message) = request
+            .json()
+            .and_then(|body| serde_json::from_value::<MessageOut>(body).ok())
+        else {
+            return error_reply(StatusCode::BAD_REQUEST, "Invalid message");
+        };
+        if message.text.is_none()
+            && message.markdown.is_none()
+            && message.files.is_none()
+            && message.attachments.is_none()
+        {
+            return error_reply(
+                StatusCode::BAD_REQUEST,
+                "Message must contain text, markdown, files or attachments",
+            );
+        }
+        if message.room_id.is_none() {
+            let recipient = self.people.iter().find(|person| {
+                Some(&person.id) == message.to_person_id.as_ref()
+                    || message.to_person_email.as_ref().is_some_and(|email| {
+                        person
+                            .emails
+                            .iter()
+                            .any(|address| address.eq_ignore_ascii_case(email))
+                    })
+            });
+            let Some(recipient) = recipient.cloned() else {
+                return error_reply(StatusCode::NOT_FOUND, "Recipient could not be found.");
+            };
+            message.room_id = Some(self.direct_room(&recipient).id);
+        } else if message
+            .room_id
+            .as_deref()
+            .and_then(|room_id| self.room(room_id))
+            .is_none()
+        {
+            return not_found();
+        }
+        let me = self.me.clone();
+        let message = self.post_message(&me, message);
+        self.sent.push(message.clone());
+        (StatusCode::OK, Some(to_json(&message)))
+    }
+
+    fn edit_message(&mut self, id: &str, request: &RecordedRequest) -> Reply {
+        let body = request.json().unwrap_or_default();
+        let Some(message) = self
+            .messages
+            .iter_mut()
+            .find(|message| message.id.as_deref() == Some(id))
+        else {
+            return not_found();
+        };
+        if let Some(markdown) = body["markdown"].as_str() {
+            message.markdown = Some(markdown.to_string());
+            message.text = Some(markdown.to_string());
+        }
+        if let Some(text) = body["text"].as_str() {
+            message.text = Some(text.to_string());
+        }
+        message.updated = Some(Utc::now());
+        (StatusCode::OK, Some(to_json(message)))
+    }
+
+    fn create_action(&mut self, request: &RecordedRequest) -> Reply {
+        let body = request.json().unwrap_or_default();
+        let message_id = body["messageId"].as_str();
+        let Some(message) = self
+            .messages
+            .iter()
+            .find(|message| message.id.as_deref() == message_id)
+        else {
+            return not_found();
+        };
+        let action = AttachmentAction {
+            id: new_id(GlobalIdType::AttachmentAction),
+            action_type: body["type"].as_str().map(str::to_string),
+            message_id: message.id.clone(),
+            inputs: serde_json::from_value(body["inputs"].clone()).ok(),
+            person_id: Some(self.me.id.clone()),
+            room_id: message.room_id.clone(),
+            created: Some(Utc::now()),
+        };
+        self.actions.push(action.clone());
+        (StatusCode::OK, Some(to_json(&action)))
+    }
+
+    fn register_device(&mut self, request: &RecordedRequest) -> Reply {
+        let Some(mut device) = request
+            .json()
+            .and_then(|body| serde_json::from_value::<DeviceData>(body).ok())
+        else {
+            return error_reply(StatusCode::BAD_REQUEST, "Invalid device");
+        };
+        device.url = Some(format!("{}/devices/{}", self.url, Uuid::new_v4()));
+        device.ws_url = Some(format!(
+            "{}/mercury",
+            self.url.replacen("http://", "ws://", 1)
+        ));
+        device.modification_time = Some(Utc::now());
+        self.devices.push(device.clone());
+        (StatusCode::OK, Some(to_json(&device)))
+    }
+}
+
+/// Apply the `max` query parameter to a list.
+fn max<'a, T: 'a>(
+    request: &RecordedRequest,
+    items: impl Iterator<Item = &'a T>,
+) -> impl Iterator<Item = &'a T> {
+    let max = request
+        .query_param("max")
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(usize::MAX);
+    items.take(max)
+}
+
+async fn serve(listener: TcpListener, shared: Arc<Shared>) {
+    loop {
+        match listener.accept().await {
+            Ok((socket, _)) => {
+                tokio::spawn(handle_connection(socket, shared.clone()));
+            }
+            Err(e) => warn!("Mock Webex server failed to accept a connection: {e}"),
+        }
+    }
+}
+
+struct RawRequest {
+    method: String,
+    target: String,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+/// Read one HTTP/1.1 request, or `None` if the connection closed first.
+async fn read_request(socket: &mut TcpStream) -> std::io::Result<Option<RawRequest>> {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 8192];
+    let header_end = loop {
+        if let Some(end) = buf.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+        let read = socket.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    };
+    let head = String::from_utf8_lossy(&buf[..header_end]).into_owned();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or("/").to_string();
+    let headers: HashMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+    let chunked = headers
+        .get("transfer-encoding")
+        .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"));
+    let body = if chunked {
+        read_chunked(socket, buf.split_off(header_end)).await?
+    } else {
+        let length = headers
+            .get("content-length")
+            .and_then(|length| length.parse().ok())
+            .unwrap_or(0);
+        while buf.len() < header_end + length {
+            let read = socket.read(&mut chunk).await?;
+            if read == 0 {
+                break;
+            }
+            buf.extend_from_slice(&chunk[..read]);
+        }
+        buf[header_end..].to_vec()
+    };
+    Ok(Some(RawRequest {
+        method,
+        target,
+        headers,
+        body,
+    }))
+}
+
+/// Decode a `transfer-encoding: chunked` body, of which `buf` has been read already. Trailers
+/// are ignored.
+async fn read_chunked(socket: &mut TcpStream, mut buf: Vec<u8>) -> std::io::Result<Vec<u8>> {
+    async fn read_more(socket: &mut TcpStream, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.reserve(8192);
+        if socket.read_buf(buf).await? == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+
+    let mut body = Vec::new();
+    let mut pos = 0;
+    loop {
+        let line_end = loop {
+            if let Some(end) = buf[pos..].windows(2).position(|window| window == b"\r\n") {
+                break pos + end;
+            }
+            read_more(socket, &mut buf).await?;
+        };
+        // The size may be followed by `;extensions`
+        let line = String::from_utf8_lossy(&buf[pos..line_end]);
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        pos = line_end + 2;
+        if size == 0 {
+            return Ok(body);
+        }
+        while buf.len() < pos + size + 2 {
+            read_more(socket, &mut buf).await?;
+        }
+        body.extend_from_slice(&buf[pos..pos + size]);
+        pos += size + 2;
+    }
+}
+
+async fn handle_connection(mut socket: TcpStream, shared: Arc<Shared>) {
+    let request = match read_request(&mut socket).await {
+        Ok(Some(request)) => request,
+        Ok(None) => return,
+        Err(e) => {
+            debug!("Mock Webex server failed to read a request: {e}");
+            return;
+        }
+    };
+    if request
+        .headers
+        .get("upgrade")
+        .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"))
+    {
+        if let Err(e) = handle_websocket(socket, &request, &shared).await {
+            debug!("Mock event stream ended: {e}");
+        }
+        return;
+    }
+
+    let (path, query) = request
+        .target
+        .split_once('?')
+        .unwrap_or((request.target.as_str(), ""));
+    let recorded = RecordedRequest {
+        method: request.method.clone(),
+        path: path.to_string(),
+        query: url::form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect(),
+        body: String::from_utf8_lossy(&request.body).into_owned(),
+    };
+    trace!("Mock Webex request: {} {}", recorded.method, request.target);
+    let authorized = request.headers.get("authorization").map(String::as_str)
+        == Some(&format!("Bearer {}", shared.token));
+    let (status, body) = {
+        let mut state = shared.state();
+        state.requests.push(recorded.clone());
+        if authorized {
+            state.handle(&recorded)
+        } else {
+            error_reply(
+                StatusCode::UNAUTHORIZED,
+                "The request requires a valid access token set in the Authorization request header.",
+            )
+        }
+    };
+    shared.changed.send_modify(|requests| *requests += 1);
+
+    let body = body.map(|body| body.to_string()).unwrap_or_default();
+    let response = format!(
+        "HTTP/1.1 {} {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\ntrackingid: MOCK_{}\r\nconnection: close\r\n\r\n{body}",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or_default(),
+        body.len(),
+        Uuid::new_v4(),
+    );
+    if let Err(e) = socket.write_all(response.as_bytes()).await {
+        debug!("Mock Webex server failed to write a response: {e}");
+    }
+}
+
+async fn send(ws: &mut WebSocketStream<TcpStream>, message: TMessage) -> Result<(), Error> {
+    ws.send(message)
+        .await
+        .map_err(|e| Error::Closed(e.to_string()))
+}
+
+/// Complete the websocket handshake, check the mercury authorization message, then forward the
+/// injected events until either side closes the connection.
+#[allow(clippy::significant_drop_tightening)]
+async fn handle_websocket(
+    mut socket: TcpStream,
+    request: &RawRequest,
+    shared: &Shared,
+) -> Result<(), Error> {
+    let key = request
+        .headers
+        .get("sec-websocket-key")
+        .ok_or("Websocket upgrade without a key")?;
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nupgrade: websocket\r\nconnection: Upgrade\r\nsec-websocket-accept: {}\r\n\r\n",
+        derive_accept_key(key.as_bytes())
+    );
+    socket.write_all(response.as_bytes()).await?;
+    let mut ws = WebSocketStream::from_raw_socket(socket, Role::Server, None).await;
+
+    let expected = format!("Bearer {}", shared.token);
+    let authorized = match ws.next().await {
+        Some(Ok(TMessage::Text(text))) => serde_json::from_str::<Value>(&text)
+            .is_ok_and(|auth| auth["type"] == "authorization" && auth["data"]["token"] == expected),
+        _ => false,
+    };
+    if !authorized {
+        debug!("Mock event stream rejected the authorization");
+        let close = CloseFrame {
+            code: CloseCode::Library(4401),
+            reason: "Unauthorized".into(),
+        };
+        return send(&mut ws, TMessage::Close(Some(close))).await;
+    }
+    send(&mut ws, TMessage::Pong(Vec::new())).await?;
+    shared.state().connections += 1;
+    debug!("Mock event stream connected");
+
+    // Held until the connection ends, so the next stream gets the remaining events
+    let mut events = shared.events.clone().lock_owned().await;
+    loop {
+        tokio::select! {
+            incoming = ws.next() => match incoming {
+                // Pings are answered by tungstenite
+                Some(Ok(TMessage::Close(_)) | Err(_)) | None => return Ok(()),
+                Some(Ok(_)) => {}
+            },
+            injected = events.recv() => match injected {
+                Some(Injected::Frame(frame)) => send(&mut ws, frame).await?,
+                Some(Injected::Close) | None => return send(&mut ws, TMessage::Close(None)).await,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bot::{Bot, Context, Router},
+        retry::RetryPolicy,
+        WebexEvent,
+    };
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn runs_a_bot_offline() {
+        let mock = MockWebex::start().await;
+        let alice = mock.add_person("Alice", "alice@example.com");
+        let room = mock.add_room("Test room", RoomType::Group);
+        let router = Router::new()
+            .command("echo", "", |ctx: Context| async move {
+                ctx.reply(&ctx.args.join(" ")).await?;
+                Ok(())
+            })
+            .card(|ctx: Context| async move {
+                let inputs = ctx
+                    .action
+                    .as_ref()
+                    .and_then(|action| action.inputs.clone())
+                    .unwrap_or_default();
+                ctx.reply(&format!("Chose {}", inputs["choice"])).await?;
+                Ok(())
+            })
+            .membership(|ctx: Context| async move {
+                ctx.reply("Hello!").await?;
+                Ok(())
+            });
+        let bot = Bot::new(mock.client().await.unwrap(), router);
+        let bot = tokio::spawn(bot.run());
+        let next = || timeout(Duration::from_secs(10), mock.next_sent_message());
+
+        mock.add_bot_to_room(&alice, &room);
+        let greeting = next().await.unwrap();
+        assert_eq!(greeting.markdown.as_deref(), Some("Hello!"));
+        assert_eq!(greeting.room_id.as_ref(), Some(&room.id));
+
+        let question = mock.post_message(&alice, &room, "Test Bot echo hi there");
+        let reply = next().await.unwrap();
+        assert_eq!(reply.markdown.as_deref(), Some("hi there"));
+        assert_eq!(reply.parent_id, question.id);
+        mock.assert_requested("GET", &format!("/messages/{}", question.id.unwrap()));
+
+        let inputs = HashMap::from([("choice".to_string(), json!("blue"))]);
+        let action = mock.submit_card(&alice, &reply, inputs);
+        assert_eq!(
+            next().await.unwrap().markdown.as_deref(),
+            Some("Chose \"blue\"")
+        );
+        mock.assert_requested("GET", &format!("/attachment/actions/{}", action.id));
+
+        // The bot reconnects, through the device it registered
+        mock.close_event_stream();
+        mock.post_message(&alice, &room, "echo again");
+        assert_eq!(next().await.unwrap().markdown.as_deref(), Some("again"));
+        assert_eq!(mock.event_stream_connections(), 2);
+        assert_eq!(mock.requests_to("POST", "/devices").len(), 1);
+        assert_eq!(mock.messages().len(), 6);
+        bot.abort();
+    }
+
+    #[tokio::test]
+    async fn emulates_the_api() {
+        let mock = MockWebex::start().await;
+        let webex = mock
+            .client_builder()
+            .retry_policy(RetryPolicy::disabled())
+            .validate_token(true)
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(webex.identity(), Some(&mock.me()));
+        assert_eq!(
+            webex.service_url("wdm").await.unwrap().as_deref(),
+            Some(mock.url())
+        );
+
+        let bob = mock.add_person("Bob", "bob@example.com");
+        let found = webex.person_by_email("BOB@example.com").await.unwrap();
+        assert_eq!(found.as_ref(), Some(&bob));
+        let sent = webex
+            .send_message(&MessageOut {
+                to_person_email: Some("bob@example.com".to_string()),
+                text: Some("Hi Bob".to_string()),
+                ..MessageOut::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(sent.room_id, Some(mock.direct_room(&bob).id));
+        assert_eq!(
+            mock.assert_requested("POST", "/messages").json().unwrap()["toPersonEmail"],
+            "bob@example.com"
+        );
+
+        mock.fail_next("GET", "/rooms", StatusCode::SERVICE_UNAVAILABLE);
+        let err = webex.list::<Room>().await.unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert_eq!(webex.list::<Room>().await.unwrap().len(), 1);
+
+        let mut events = webex.typed_event_stream(true).await.unwrap();
+        mock.post_reply(&bob, &sent, "Hi yourself");
+        // The first event is the one for the message sent above
+        let reply = loop {
+            match timeout(Duration::from_secs(10), events.next())
+                .await
+                .unwrap()
+            {
+                Some(Ok(WebexEvent::MessageCreated {
+                    message: Some(message),
+                    person_id: Some(person_id),
+                    ..
+                })) if person_id.id() == bob.id => break message,
+                Some(Ok(WebexEvent::MessageCreated { .. })) => {}
+                event => panic!("expected a message, got {event:?}"),
+            }
+        };
+        assert_eq!(reply.parent_id, sent.id);
+        assert_eq!(reply.text.as_deref(), Some("Hi yourself"));
+
+        let intruder = Webex::builder("wrong")
+            .rest_url(mock.url())
+            .wdm_url(mock.url())
+            .build()
+            .await
+            .unwrap();
+        assert!(matches!(
+            intruder.me().await,
+            Err(Error::Authentication(Some(_)))
+        ));
+        assert_eq!(mock.event_stream_connections(), 1);
+    }
+
+    #[tokio::test]
+    async fn reads_chunked_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (mut server, _) = listener.accept().await.unwrap();
+        client
+            .write_all(
+                b"POST /messages HTTP/1.1\r\ntransfer-encoding: chunked\r\n\r\n\
+                  5\r\nhello\r\n7;ext=1\r\n, world\r\n0\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let request = read_request(&mut server).await.unwrap().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.body, b"hello, world");
+    }
+}
@@ -0,0 +1,290 @@
+#![deny(missing_docs)]
+//! Threads: a top-level message and the replies to it
+
+use crate::{error::Error, GlobalId, GlobalIdType, Message, MessageListParams, Webex};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use std::{collections::HashMap, fmt};
+
+/// A top-level message with its replies, oldest first.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Thread {
+    /// The message that started the thread.
+    pub parent: Message,
+    /// Replies to the parent, ordered by creation time.
+    pub replies: Vec<Message>,
+}
+
+/// Overview of a [`Thread`], see [`Thread::summary()`]. Displays as a one-line description.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ThreadSummary {
+    /// Number of replies.
+    pub reply_count: usize,
+    /// Authors of the parent and replies (email if known, else person ID), in order of their
+    /// first message.
+    pub participants: Vec<String>,
+    /// When the parent was created.
+    pub started: Option<DateTime<Utc>>,
+    /// When the last reply was created.
+    pub last_reply: Option<DateTime<Utc>>,
+    /// Start of the parent's text.
+    pub preview: String,
+}
+
+const PREVIEW_LENGTH: usize = 80;
+
+impl Thread {
+    /// Reconstruct threads from a flat list of messages, e.g. a page of
+    /// [`Webex::list_with_params::<Message>()`](Webex::list_with_params) or a
+    /// [`RoomHistory`](crate::RoomHistory). Replies whose parent isn't in the list are dropped.
+    /// Threads are ordered by the creation time of their parent.
+    #[must_use]
+    pub fn from_messages(messages: impl IntoIterator<Item = Message>) -> Vec<Self> {
+        let (parents, replies): (Vec<_>, Vec<_>) = messages
+            .into_iter()
+            .partition(|message| message.parent_id.is_none());
+        let mut threads: Vec<Self> = parents
+            .into_iter()
+            .map(|parent| Self {
+                parent,
+                replies: Vec::new(),
+            })
+            .collect();
+        let index: HashMap<String, usize> = threads
+            .iter()
+            .enumerate()
+            .filter_map(|(i, thread)| Some((thread.parent.id.clone()?, i)))
+            .collect();
+        for reply in replies {
+            if let Some(&i) = reply.parent_id.as_ref().and_then(|id| index.get(id)) {
+                threads[i].replies.push(reply);
+            }
+        }
+        for thread in &mut threads {
+            thread.replies.sort_by_key(|reply| reply.created);
+        }
+        threads.sort_by_key(|thread| thread.parent.created);
+        threads
+    }
+
+    /// The parent followed by the replies.
+    pub fn messages(&self) -> impl Iterator<Item = &Message> {
+        std::iter::once(&self.parent).chain(&self.replies)
+    }
+
+    /// Post a markdown reply to the thread, and add it to [`replies`](Self::replies).
+    ///
+    /// # Errors
+    /// As for [`Webex::send_message()`].
+    pub async fn reply(&mut self, webex: &Webex, markdown: &str) -> Result<&Message, Error> {
+        let mut reply = self.parent.reply();
+        reply.markdown = Some(markdown.to_string());
+        let sent = webex.send_message(&reply).await?;
+        self.replies.push(sent);
+        Ok(&self.replies[self.replies.len() - 1])
+    }
+
+    /// Who took part in the thread, and when.
+    #[must_use]
+    pub fn summary(&self) -> ThreadSummary {
+        let mut participants: Vec<String> = Vec::new();
+        for author in self
+            .messages()
+            .filter_map(|message| message.person_email.as_ref().or(message.person_id.as_ref()))
+        {
+            if !participants.contains(author) {
+                participants.push(author.clone());
+            }
+        }
+        let text = self.parent.text.as_deref().unwrap_or_default();
+        let mut preview: String = text.chars().take(PREVIEW_LENGTH).collect();
+        if preview.len() < text.len() {
+            preview.push('…');
+        }
+        ThreadSummary {
+            reply_count: self.replies.len(),
+            participants,
+            started: self.parent.created,
+            last_reply: self.replies.iter().filter_map(|reply| reply.created).max(),
+            preview,
+        }
+    }
+}
+
+impl fmt::Display for ThreadSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\"{}\": {} {} from {}",
+            self.preview,
+            self.reply_count,
+            if self.reply_count == 1 {
+                "reply"
+            } else {
+                "replies"
+            },
+            self.participants.join(", ")
+        )?;
+        if let Some(last_reply) = self.last_reply {
+            write!(f, ", last at {}", last_reply.format("%Y-%m-%d %H:%M UTC"))?;
+        }
+        Ok(())
+    }
+}
+
+impl Webex {
+    /// Load the thread a message belongs to: the message itself if it is a top-level message,
+    /// otherwise its parent, along with all replies.
+    ///
+    /// # Errors
+    /// As for [`Webex::get()`] and [`Webex::list_stream_with_params()`].
+    pub async fn thread(&self, message_id: &GlobalId) -> Result<Thread, Error> {
+        let mut parent: Message = self.get(message_id).await?;
+        if let Some(parent_id) = parent.parent_id.clone() {
+            parent = self
+                .get(&GlobalId::new_with_cluster_unchecked(
+                    GlobalIdType::Message,
+                    parent_id,
+                    None,
+                ))
+                .await?;
+        }
+        let (Some(room_id), Some(parent_id)) = (&parent.room_id, &parent.id) else {
+            return Err(Error::UnexpectedResponse(
+                "Message is missing its ID or room ID",
+            ));
+        };
+        let mut replies: Vec<Message> = self
+            .list_stream_with_params(MessageListParams {
+                parent_id: Some(parent_id),
+                ..MessageListParams::new(room_id)
+            })
+            .try_collect()
+            .await?;
+        replies.sort_by_key(|reply| reply.created);
+        Ok(Thread { parent, replies })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::mock_server;
+
+    fn message(id: &str, parent: Option<&str>, email: &str, created: &str) -> Message {
+        Message {
+            id: Some(id.to_string()),
+            room_id: Some("room".to_string()),
+            parent_id: parent.map(str::to_string),
+            person_email: Some(email.to_string()),
+            text: Some(format!("text of {id}")),
+            created: created.parse().ok(),
+            ..Message::default()
+        }
+    }
+
+    #[test]
+    fn reconstructs_threads() {
+        let threads = Thread::from_messages([
+            message("r2", Some("p1"), "b@example.com", "2024-01-01T00:03:00Z"),
+            message("p2", None, "c@example.com", "2024-01-01T00:02:00Z"),
+            message("r1", Some("p1"), "a@example.com", "2024-01-01T00:01:00Z"),
+            message("p1", None, "a@example.com", "2024-01-01T00:00:00Z"),
+            message(
+                "orphan",
+                Some("p0"),
+                "d@example.com",
+                "2024-01-01T00:04:00Z",
+            ),
+        ]);
+        assert_eq!(threads.len(), 2);
+        let ids: Vec<_> = threads[0]
+            .messages()
+            .map(|message| message.id.as_deref().unwrap())
+            .collect();
+        assert_eq!(ids, ["p1", "r1", "r2"]);
+        assert!(threads[1].replies.is_empty());
+
+        let summary = threads[0].summary();
+        assert_eq!(summary.participants, ["a@example.com", "b@example.com"]);
+        assert_eq!(
+            summary.to_string(),
+            "\"text of p1\": 2 replies from a@example.com, b@example.com, last at 2024-01-01 00:03 UTC"
+        );
+    }
+
+    #[tokio::test]
+    async fn loads_thread_from_reply() {
+        let json = |message: &Message| serde_json::to_string(message).unwrap();
+        let replies = format!(
+            r#"{{"items":[{},{}]}}"#,
+            json(&message(
+                "r2",
+                Some("p1"),
+                "b@example.com",
+                "2024-01-01T00:03:00Z"
+            )),
+            json(&message(
+                "r1",
+                Some("p1"),
+                "a@example.com",
+                "2024-01-01T00:01:00Z"
+            )),
+        );
+        let (prefix, requests) = mock_server(vec![
+            (
+                "200 OK",
+                "",
+                json(&message(
+                    "r1",
+                    Some("p1"),
+                    "a@example.com",
+                    "2024-01-01T00:01:00Z",
+                )),
+            ),
+            (
+                "200 OK",
+                "",
+                json(&message(
+                    "p1",
+                    None,
+                    "a@example.com",
+                    "2024-01-01T00:00:00Z",
+                )),
+            ),
+            ("200 OK", "", replies),
+            (
+                "200 OK",
+                "",
+                json(&message(
+                    "r3",
+                    Some("p1"),
+                    "c@example.com",
+                    "2024-01-01T00:05:00Z",
+                )),
+            ),
+        ])
+        .await;
+        let webex = Webex::builder("token")
+            .rest_url(prefix)
+            .wdm_url("http://localhost")
+            .build()
+            .await
+            .unwrap();
+        let id = GlobalId::new_with_cluster_unchecked(GlobalIdType::Message, "r1".into(), None);
+        let mut thread = webex.thread(&id).await.unwrap();
+        assert_eq!(thread.parent.id.as_deref(), Some("p1"));
+        assert_eq!(thread.replies[0].id.as_deref(), Some("r1"));
+        thread.reply(&webex, "on it").await.unwrap();
+        assert_eq!(thread.summary().reply_count, 3);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                "GET /messages/r1 HTTP/1.1",
+                "GET /messages/p1 HTTP/1.1",
+                "GET /messages?roomId=room&parentId=p1 HTTP/1.1",
+                "POST /messages HTTP/1.1",
+            ]
+        );
+    }
+}
@@ -3,6 +3,7 @@
 
 use crate::{adaptive_card::AdaptiveCard, error};
 use base64::Engine;
+use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 use std::convert::TryFrom;
@@ -14,8 +15,9 @@ pub(crate) use api::{Gettable, ListResult};
 mod api {
     //! Private crate to hold all types that the user shouldn't have to interact with.
     use super::{
-        AttachmentAction, Message, MessageListParams, Organization, Person, Room, RoomListParams,
-        Team,
+        AttachmentAction, Membership, MembershipListParams, Message, MessageListParams,
+        Organization, Person, PersonListParams, Room, RoomListParams, Team, TeamMembership,
+        TeamMembershipListParams, Webhook, WebhookListParams,
     };
 
     /// Trait for API types. Has to be public due to trait bounds limitations on webex API, but hidden
@@ -52,7 +54,12 @@ mod api {
 
     impl Gettable for Person {
         const API_ENDPOINT: &'static str = "people";
-        type ListParams<'a> = Option<Infallible>;
+        type ListParams<'a> = PersonListParams<'a>;
+    }
+
+    impl Gettable for Membership {
+        const API_ENDPOINT: &'static str = "memberships";
+        type ListParams<'a> = MembershipListParams<'a>;
     }
 
     impl Gettable for Team {
@@ -60,12 +67,59 @@ mod api {
         type ListParams<'a> = Option<Infallible>;
     }
 
+    impl Gettable for TeamMembership {
+        const API_ENDPOINT: &'static str = "team/memberships";
+        type ListParams<'a> = TeamMembershipListParams<'a>;
+    }
+
+    impl Gettable for Webhook {
+        const API_ENDPOINT: &'static str = "webhooks";
+        type ListParams<'a> = WebhookListParams<'a>;
+    }
+
     #[derive(crate::types::Deserialize)]
     pub struct ListResult<T> {
         pub items: Vec<T>,
     }
 }
 
+/// Deserialize a timestamp leniently: RFC 3339 (with any precision), RFC 3339 without an offset
+/// (taken as UTC), or milliseconds since the epoch. Anything else, including an empty string,
+/// becomes `None` rather than failing the whole object.
+pub(crate) fn deserialize_timestamp<'de, D>(
+    deserializer: D,
+) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Timestamp {
+        Text(String),
+        Millis(i64),
+        Other(serde::de::IgnoredAny),
+    }
+
+    Ok(match Timestamp::deserialize(deserializer)? {
+        Timestamp::Text(text) => DateTime::parse_from_rfc3339(&text)
+            .map(|time| time.with_timezone(&Utc))
+            .or_else(|_| {
+                NaiveDateTime::parse_from_str(&text, "%Y-%m-%dT%H:%M:%S%.f")
+                    .map(|time| time.and_utc())
+            })
+            .ok(),
+        Timestamp::Millis(millis) => DateTime::from_timestamp_millis(millis),
+        Timestamp::Other(_) => None,
+    })
+}
+
+fn serialize_comma_separated<S: serde::Serializer>(
+    items: &[&str],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&items.join(","))
+}
+
 /// Webex Teams room information
 #[skip_serializing_none]
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
@@ -76,21 +130,20 @@ pub struct Room {
     /// A user-friendly name for the room.
     pub title: Option<String>,
     /// The room type.
-    ///
-    /// direct - 1:1 room
-    /// group - group room
     #[serde(rename = "type")]
-    pub room_type: String,
+    pub room_type: RoomType,
     /// Whether the room is moderated (locked) or not.
     pub is_locked: bool,
     /// The ID for the team with which this room is associated.
     pub team_id: Option<String>,
     /// The date and time of the room's last activity.
-    pub last_activity: String,
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub last_activity: Option<DateTime<Utc>>,
     /// The ID of the person who created this room.
     pub creator_id: String,
     /// The date and time the room was created.
-    pub created: String,
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub created: Option<DateTime<Utc>>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, crate::types::Serialize)]
@@ -118,9 +171,9 @@ pub struct RoomListParams<'a> {
     /// Shows the org's public spaces joined and unjoined. When set the result list is sorted by the madePublic timestamp.
     pub org_public_spaces: Option<bool>,
     /// Filters rooms, that were made public after this time. See madePublic timestamp
-    pub from: Option<&'a str>,
+    pub from: Option<DateTime<Utc>>,
     /// Filters rooms, that were made public before this time. See madePublic timestamp
-    pub to: Option<&'a str>,
+    pub to: Option<DateTime<Utc>>,
     /// Sort results. Cannot be set in combination with orgPublicSpaces.
     pub sort_by: Option<SortRoomsBy>,
     /// Limit the maximum number of rooms in the response.
@@ -128,6 +181,49 @@ pub struct RoomListParams<'a> {
     pub max: Option<u32>,
 }
 
+/// Parameters for creating a room.
+/// Follows <https://developer.webex.com/docs/api/v1/rooms/create-a-room>
+#[skip_serializing_none]
+#[derive(Clone, Debug, Default, Eq, PartialEq, crate::types::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomCreateParams<'a> {
+    /// A user-friendly name for the room.
+    pub title: &'a str,
+    /// The ID for the team with which this room is associated.
+    pub team_id: Option<&'a str>,
+    /// Whether the room is moderated (locked), with the creator as its moderator.
+    pub is_locked: Option<bool>,
+    /// Whether the room is public, so that people in the org can find and join it.
+    /// Requires `description`.
+    pub is_public: Option<bool>,
+    /// The description of the room.
+    pub description: Option<&'a str>,
+    /// Whether only moderators can post messages. Requires `is_locked`.
+    pub is_announcement_only: Option<bool>,
+}
+
+/// Parameters for updating a room. `title` is required by the API even if it doesn't change.
+/// Follows <https://developer.webex.com/docs/api/v1/rooms/update-a-room>
+#[skip_serializing_none]
+#[derive(Clone, Debug, Default, Eq, PartialEq, crate::types::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomUpdateParams<'a> {
+    /// A user-friendly name for the room.
+    pub title: &'a str,
+    /// Move the room into the team with this ID.
+    pub team_id: Option<&'a str>,
+    /// Set the room moderated (locked) or unmoderated.
+    pub is_locked: Option<bool>,
+    /// Whether the room is public, so that people in the org can find and join it.
+    pub is_public: Option<bool>,
+    /// The description of the room.
+    pub description: Option<&'a str>,
+    /// Whether only moderators can post messages. Requires `is_locked`.
+    pub is_announcement_only: Option<bool>,
+    /// Set to true to make the room read-only, e.g. to archive it.
+    pub is_read_only: Option<bool>,
+}
+
 /// Holds details about the organization an account belongs to.
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -154,13 +250,250 @@ pub struct Team {
     pub description: Option<String>,
 }
 
-#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+/// Membership of a person in a room
+#[skip_serializing_none]
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Membership {
+    /// A unique identifier for the membership.
+    pub id: String,
+    /// The room ID.
+    pub room_id: Option<String>,
+    /// The person ID.
+    pub person_id: Option<String>,
+    /// The email address of the person.
+    pub person_email: Option<String>,
+    /// The display name of the person.
+    pub person_display_name: Option<String>,
+    /// The organization ID of the person.
+    pub person_org_id: Option<String>,
+    /// Whether or not the participant is a room moderator.
+    #[serde(default)]
+    pub is_moderator: bool,
+    /// Whether or not the room is hidden in the Webex clients.
+    pub is_room_hidden: Option<bool>,
+    /// The type of room the membership is associated with.
+    pub room_type: Option<RoomType>,
+    /// The date and time when the membership was created.
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub created: Option<DateTime<Utc>>,
+}
+
+#[skip_serializing_none]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+/// Parameters for listing memberships. Bots and users can only list memberships of rooms they
+/// are in; without `room_id`, the memberships of the caller are listed.
+pub struct MembershipListParams<'a> {
+    /// List memberships of a room, by ID.
+    pub room_id: Option<&'a str>,
+    /// List memberships of a person, by ID. Requires `room_id`.
+    pub person_id: Option<&'a str>,
+    /// List memberships of a person, by email address. Requires `room_id`.
+    pub person_email: Option<&'a str>,
+    /// Limit the maximum number of memberships in the response.
+    /// Default: 100
+    pub max: Option<u32>,
+}
+
+/// Parameters for adding a person to a room.
+/// `room_id` is required, as well as one of `person_id` or `person_email`.
+/// Follows <https://developer.webex.com/docs/api/v1/memberships/create-a-membership>
+#[skip_serializing_none]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MembershipCreateParams<'a> {
+    /// The room ID.
+    pub room_id: &'a str,
+    /// The person ID.
+    pub person_id: Option<&'a str>,
+    /// The email address of the person.
+    pub person_email: Option<&'a str>,
+    /// Whether or not the participant is a room moderator.
+    pub is_moderator: Option<bool>,
+}
+
+/// Parameters for updating a membership.
+/// Follows <https://developer.webex.com/docs/api/v1/memberships/update-a-membership>
+#[skip_serializing_none]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MembershipUpdateParams {
+    /// Whether or not the participant is a room moderator.
+    pub is_moderator: bool,
+    /// When set to true, hides direct spaces in the teams client. Only applies to memberships of
+    /// the caller in direct rooms.
+    pub is_room_hidden: Option<bool>,
+}
+
+/// Parameters for creating a team, or renaming it.
+/// Follows <https://developer.webex.com/docs/api/v1/teams/create-a-team>
+#[skip_serializing_none]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamParams<'a> {
+    /// Name of the team
+    pub name: &'a str,
+    /// Team description
+    pub description: Option<&'a str>,
+}
+
+/// Membership of a person in a team
+#[skip_serializing_none]
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamMembership {
+    /// A unique identifier for the team membership.
+    pub id: String,
+    /// The team ID.
+    pub team_id: Option<String>,
+    /// The person ID.
+    pub person_id: Option<String>,
+    /// The email address of the person.
+    pub person_email: Option<String>,
+    /// The display name of the person.
+    pub person_display_name: Option<String>,
+    /// The organization ID of the person.
+    pub person_org_id: Option<String>,
+    /// Whether or not the participant is a team moderator.
+    #[serde(default)]
+    pub is_moderator: bool,
+    /// The date and time when the team membership was created.
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub created: Option<DateTime<Utc>>,
+}
+
+#[skip_serializing_none]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+/// Parameters for listing team memberships
+pub struct TeamMembershipListParams<'a> {
+    /// List memberships of a team, by ID.
+    pub team_id: &'a str,
+    /// Limit the maximum number of team memberships in the response.
+    /// Default: 100
+    pub max: Option<u32>,
+}
+
+/// Parameters for adding a person to a team.
+///
+/// `team_id` is required, as well as one of `person_id` or `person_email`.
+/// Follows <https://developer.webex.com/docs/api/v1/team-memberships/create-a-team-membership>
+#[skip_serializing_none]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamMembershipCreateParams<'a> {
+    /// The team ID.
+    pub team_id: &'a str,
+    /// The person ID.
+    pub person_id: Option<&'a str>,
+    /// The email address of the person.
+    pub person_email: Option<&'a str>,
+    /// Whether or not the participant is a team moderator.
+    pub is_moderator: Option<bool>,
+}
+
+/// Parameters for updating a team membership.
+/// Follows <https://developer.webex.com/docs/api/v1/team-memberships/update-a-team-membership>
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamMembershipUpdateParams {
+    /// Whether or not the participant is a team moderator.
+    pub is_moderator: bool,
+}
+
+/// A webhook, through which Webex notifies a URL of events instead of sending them over the
+/// event stream.
+#[skip_serializing_none]
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Webhook {
+    /// A unique identifier for the webhook.
+    pub id: String,
+    /// A user-friendly name for the webhook.
+    pub name: String,
+    /// The URL that receives POST requests for each event.
+    pub target_url: String,
+    /// The resource type for the webhook, e.g. `messages`, `memberships`, `rooms` or
+    /// `attachmentActions`.
+    pub resource: String,
+    /// The event type for the webhook: `created`, `updated`, `deleted` or `all`.
+    pub event: String,
+    /// The filter that defines the webhook scope, e.g. `roomId=...`.
+    pub filter: Option<String>,
+    /// The secret used to generate the payload signature.
+    pub secret: Option<String>,
+    /// The status of the webhook: `active` or `inactive`.
+    pub status: Option<String>,
+    /// Whether the webhook is owned by the org or the creator.
+    pub owned_by: Option<String>,
+    /// The date and time the webhook was created.
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub created: Option<DateTime<Utc>>,
+}
+
+#[skip_serializing_none]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct CatalogReply {
-    pub service_links: Catalog,
+/// Parameters for listing webhooks
+pub struct WebhookListParams<'a> {
+    /// Limit the maximum number of webhooks in the response.
+    /// Default: 100
+    pub max: Option<u32>,
+    /// Set to `org` to list org-wide webhooks.
+    pub owned_by: Option<&'a str>,
 }
 
+/// Parameters for creating a webhook.
+///
+/// Follows <https://developer.webex.com/docs/api/v1/webhooks/create-a-webhook>
+#[skip_serializing_none]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookCreateParams<'a> {
+    /// A user-friendly name for the webhook.
+    pub name: &'a str,
+    /// The URL that receives POST requests for each event.
+    pub target_url: &'a str,
+    /// The resource type for the webhook, e.g. `messages`.
+    pub resource: &'a str,
+    /// The event type for the webhook, e.g. `created`.
+    pub event: &'a str,
+    /// The filter that defines the webhook scope, e.g. `roomId=...`.
+    pub filter: Option<&'a str>,
+    /// The secret used to generate the payload signature, see
+    /// [`WebhookReceiver`](crate::WebhookReceiver).
+    pub secret: Option<&'a str>,
+    /// Set to `org` to create an org-wide webhook.
+    pub owned_by: Option<&'a str>,
+}
+
+/// Parameters for updating a webhook. `name` and `target_url` are required by the API even if
+/// they don't change.
+///
+/// Follows <https://developer.webex.com/docs/api/v1/webhooks/update-a-webhook>
+#[skip_serializing_none]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookUpdateParams<'a> {
+    /// A user-friendly name for the webhook.
+    pub name: &'a str,
+    /// The URL that receives POST requests for each event.
+    pub target_url: &'a str,
+    /// The secret used to generate the payload signature.
+    pub secret: Option<&'a str>,
+    /// Set to `org` to make the webhook org-wide.
+    pub owned_by: Option<&'a str>,
+    /// Set to `active` to re-enable a webhook that was disabled after failed deliveries.
+    pub status: Option<&'a str>,
+}
+
+/// Service URLs of the U2C catalog.
 #[allow(missing_docs)]
+#[deprecated(
+    since = "0.11.0",
+    note = "Please use `Webex::service_catalog()` instead, which keeps services this struct doesn't know about"
+)]
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Catalog {
@@ -236,6 +569,9 @@ pub enum RoomType {
     Direct,
     /// Group room
     Group,
+    /// Any other type.
+    #[serde(untagged)]
+    Unknown(String),
 }
 
 /// Webex Teams message information
@@ -272,9 +608,11 @@ pub struct Message {
     /// Message content attachments attached to the message.
     pub attachments: Option<Vec<Attachment>>,
     /// The date and time the message was created.
-    pub created: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub created: Option<DateTime<Utc>>,
     /// The date and time the message was updated, if it was edited.
-    pub updated: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub updated: Option<DateTime<Utc>>,
     /// The ID of the "parent" message (the start of the reply chain)
     pub parent_id: Option<String>,
 }
@@ -294,7 +632,7 @@ pub struct MessageListParams<'a> {
     #[serde(skip_serializing_if = "<[_]>::is_empty")]
     pub mentioned_people: &'a [&'a str],
     /// List messages sent before a date and time.
-    pub before: Option<&'a str>,
+    pub before: Option<DateTime<Utc>>,
     /// List messages sent before a message, by ID.
     pub before_message: Option<&'a str>,
     /// Limit the maximum number of messages in the response.
@@ -335,9 +673,6 @@ pub struct MessageEditParams<'a> {
     pub html: Option<&'a str>,
 }
 
-#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
-pub(crate) struct EmptyReply {}
-
 /// API Error
 #[allow(missing_docs)]
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
@@ -345,6 +680,44 @@ pub struct DeviceError {
     pub description: String,
 }
 
+/// Body returned by the API alongside a non-2xx status code.
+/// `error`/`error_description` are used by the OAuth endpoints instead of `message`/`errors`.
+#[skip_serializing_none]
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub(crate) struct ErrorReply {
+    pub message: Option<String>,
+    pub errors: Option<Vec<DeviceError>>,
+    #[serde(rename = "trackingId")]
+    pub tracking_id: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+impl ErrorReply {
+    /// Human-readable description of the error, if the API gave one.
+    pub fn description(&self) -> Option<String> {
+        let text = self
+            .message
+            .clone()
+            .or_else(|| {
+                self.errors
+                    .as_ref()
+                    .filter(|errors| !errors.is_empty())
+                    .map(|errors| {
+                        errors
+                            .iter()
+                            .map(|e| e.description.as_str())
+                            .collect::<Vec<_>>()
+                            .join("; ")
+                    })
+            })
+            .or_else(|| self.error_description.clone())
+            .or_else(|| self.error.clone())?;
+        Some(text)
+    }
+}
+
 #[allow(missing_docs)]
 #[skip_serializing_none]
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
@@ -366,7 +739,7 @@ pub struct DeviceData {
     pub device_name: Option<String>,
     pub device_type: Option<String>,
     pub localized_model: Option<String>,
-    pub modification_time: Option<chrono::DateTime<chrono::Utc>>,
+    pub modification_time: Option<DateTime<Utc>>,
     pub model: Option<String>,
     pub name: Option<String>,
     pub system_name: Option<String>,
@@ -442,7 +815,8 @@ pub struct EventData {
 pub struct ActivityParent {
     pub actor_id: String,
     pub id: String,
-    pub published: String,
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub published: Option<DateTime<Utc>>,
     #[serde(rename = "type")]
     pub parent_type: String,
 }
@@ -612,8 +986,7 @@ impl Event {
                             ActivityType::Space(type_)
                         } else {
                             log::error!(
-                                "Unknown activity type `{}`, returning Unknown",
-                                activity_type
+                                "Unknown activity type `{activity_type}`, returning Unknown"
                             );
                             ActivityType::Unknown(format!("conversation.activity.{activity_type}"))
                         }
@@ -626,7 +999,7 @@ impl Event {
             "janus.user_sessions" => ActivityType::Janus,
             //"apheleia.subscription_update" ??
             e => {
-                log::debug!("Unknown data.event_type `{}`, returning Unknown", e);
+                log::debug!("Unknown data.event_type `{e}`, returning Unknown");
                 ActivityType::Unknown(e.to_string())
             }
         }
@@ -659,17 +1032,19 @@ impl Event {
         // Note, we do not want to parse b64 URI into cluster, since cluster information is already
         // part of the URI and we don't need any additional information (the "cluster" argument is
         // ignored).
-        let activity = self
-            .data
-            .activity
-            .as_ref()
-            .ok_or(crate::error::Error::Api("Missing activity in event"))?;
+        let activity =
+            self.data
+                .activity
+                .as_ref()
+                .ok_or(crate::error::Error::UnexpectedResponse(
+                    "Missing activity in event",
+                ))?;
         let id = match self.activity_type() {
             ActivityType::Space(SpaceActivity::Created) => self.room_id_of_space_created_event()?,
             ActivityType::Space(
                 SpaceActivity::Changed | SpaceActivity::Joined | SpaceActivity::Left,
-            ) => Self::target_global_id(activity)?,
-            ActivityType::Message(MessageActivity::Deleted) => Self::target_global_id(activity)?,
+            )
+            | ActivityType::Message(MessageActivity::Deleted) => Self::target_global_id(activity)?,
             _ => activity.id.clone(),
         };
         Ok(GlobalId::new_with_cluster_unchecked(
@@ -680,11 +1055,9 @@ impl Event {
     }
 
     fn target_global_id(activity: &Activity) -> Result<String, error::Error> {
-        activity
-            .target
-            .clone()
-            .and_then(|t| t.global_id)
-            .ok_or(crate::error::Error::Api("Missing target id in activity"))
+        activity.target.clone().and_then(|t| t.global_id).ok_or(
+            crate::error::Error::UnexpectedResponse("Missing target id in activity"),
+        )
     }
 
     /// Get the UUID of the room the Space created event corresponds to.
@@ -704,7 +1077,7 @@ impl Event {
             .data
             .activity
             .clone()
-            .ok_or(crate::error::Error::Api(
+            .ok_or(crate::error::Error::UnexpectedResponse(
                 "Missing activity in space created event",
             ))?
             .id;
@@ -721,7 +1094,7 @@ impl Event {
             uuid.replace_range(7..8, "0");
             Ok(uuid)
         } else {
-            Err(crate::error::Error::Api(
+            Err(crate::error::Error::UnexpectedResponse(
                 "Space created event uuid could not be not patched",
             ))
         }
@@ -742,6 +1115,12 @@ pub enum GlobalIdType {
     Team,
     /// Retrieves a specific attachment
     AttachmentAction,
+    /// Corresponds to the ID of a room membership
+    Membership,
+    /// Corresponds to the ID of a team membership
+    TeamMembership,
+    /// Corresponds to the ID of a webhook
+    Webhook,
     /// This `GlobalId` represents the ID of something not currently recognised, any API requests
     /// with this `GlobalId` will produce an error.
     Unknown,
@@ -759,10 +1138,7 @@ impl From<ActivityType> for GlobalIdType {
             ) => Self::Room,
             ActivityType::Unknown(_) => Self::Unknown,
             a => {
-                log::error!(
-                    "Failed to convert {:?} to GlobalIdType, this may cause errors later",
-                    a
-                );
+                log::error!("Failed to convert {a:?} to GlobalIdType, this may cause errors later");
                 Self::Unknown
             }
         }
@@ -779,6 +1155,9 @@ impl std::fmt::Display for GlobalIdType {
                 Self::Room => "ROOM",
                 Self::Team => "TEAM",
                 Self::AttachmentAction => "ATTACHMENT_ACTION",
+                Self::Membership => "MEMBERSHIP",
+                Self::TeamMembership => "TEAM_MEMBERSHIP",
+                Self::Webhook => "WEBHOOK",
                 Self::Unknown => "<UNKNOWN>",
             }
         )
@@ -927,7 +1306,10 @@ pub struct Target {
 #[skip_serializing_none]
 #[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
+#[non_exhaustive]
 pub struct Object {
+    /// ID of the object, e.g. of the person added to or removed from a room.
+    pub id: Option<String>,
     pub object_type: String,
     pub content: Option<String>,
     pub display_name: Option<String>,
@@ -951,6 +1333,7 @@ pub struct MiscItem {
 }
 
 /// Alerting specified in received events.
+///
 /// TODO: may be missing some enum variants.
 /// ALSO TODO: figure out what this does. Best guess, it refers to what alerts (e.g. a
 /// notification) an event will generate.
@@ -1020,7 +1403,8 @@ pub struct AttachmentAction {
     /// The ID of the room the action was performed within.
     pub room_id: Option<String>,
     /// The date and time the action was created.
-    pub created: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub created: Option<DateTime<Utc>>,
 }
 
 /// Person information
@@ -1046,28 +1430,97 @@ pub struct Person {
     /// The ID of the organization to which this person belongs.
     pub org_id: String,
     /// The date and time the person was created.
-    pub created: String,
+    #[serde(deserialize_with = "deserialize_timestamp")]
+    pub created: Option<DateTime<Utc>>,
     /// The date and time of the person's last activity within Webex Teams.
-    pub last_activity: String,
+    #[serde(deserialize_with = "deserialize_timestamp")]
+    pub last_activity: Option<DateTime<Utc>>,
     /// The current presence status of the person.
-    ///
-    /// active - active within the last 10 minutes
-    /// call - the user is in a call
-    /// `DoNotDisturb` - the user has manually set their status to "Do Not Disturb"
-    /// inactive - last activity occurred more than 10 minutes ago
-    /// meeting - the user is in a meeting
-    /// `OutOfOffice` - the user or a Hybrid Calendar service has indicated that they are "Out of Office"
-    /// pending - the user has never logged in; a status cannot be determined
-    /// presenting - the user is sharing content
-    /// unknown - the user’s status could not be determined
-    pub status: String,
+    pub status: PresenceStatus,
     /// The type of person account, such as person or bot.
-    ///
-    /// person- account belongs to a person
-    /// bot - account is a bot user
-    /// appuser - account is a guest user
     #[serde(rename = "type")]
-    pub person_type: String,
+    pub person_type: PersonType,
+}
+
+#[skip_serializing_none]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+/// Parameters for listing people. One of `email`, `display_name` or `id` is required, unless the
+/// caller is an admin listing the people of their org.
+pub struct PersonListParams<'a> {
+    /// List people with this email address.
+    pub email: Option<&'a str>,
+    /// List people whose name starts with this string.
+    pub display_name: Option<&'a str>,
+    /// List people by ID. At most 85 IDs can be given.
+    #[serde(
+        skip_serializing_if = "<[_]>::is_empty",
+        serialize_with = "serialize_comma_separated"
+    )]
+    pub id: &'a [&'a str],
+    /// List people in this organization. Only admin users of another organization (such as
+    /// partners) may use this parameter.
+    pub org_id: Option<&'a str>,
+    /// Include Webex Calling user details in the response.
+    pub calling_data: Option<bool>,
+    /// Limit the maximum number of people in the response.
+    /// Default: 100
+    pub max: Option<u32>,
+}
+
+/// Presence status of a [`Person`]
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PresenceStatus {
+    /// Active within the last 10 minutes
+    Active,
+    /// In a call
+    Call,
+    /// Manually set their status to "Do Not Disturb"
+    #[serde(rename = "DoNotDisturb")]
+    DoNotDisturb,
+    /// Last activity occurred more than 10 minutes ago
+    Inactive,
+    /// In a meeting
+    Meeting,
+    /// The user or a Hybrid Calendar service has indicated that they are "Out of Office"
+    #[serde(rename = "OutOfOffice")]
+    OutOfOffice,
+    /// Never logged in, so a status cannot be determined
+    Pending,
+    /// Sharing content
+    Presenting,
+    /// Any other status, including `unknown` (the status could not be determined). Empty if the
+    /// status was missing.
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+impl Default for PresenceStatus {
+    fn default() -> Self {
+        Self::Unknown(String::new())
+    }
+}
+
+/// Type of a [`Person`] account
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PersonType {
+    /// Account belongs to a person
+    Person,
+    /// Account is a bot user
+    Bot,
+    /// Account is a guest user
+    AppUser,
+    /// Any other type. Empty if the type was missing.
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+impl Default for PersonType {
+    fn default() -> Self {
+        Self::Unknown(String::new())
+    }
 }
 
 /// Phone number information
@@ -1126,6 +1579,95 @@ mod tests {
         }
     }
 
+    #[test]
+    fn lenient_timestamps() {
+        let message: Message = serde_json::from_str(
+            r#"{"created":"2024-01-02T03:04:05.678Z","updated":"2024-01-02T03:04:05"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            message.created.unwrap().to_rfc3339(),
+            "2024-01-02T03:04:05.678+00:00"
+        );
+        assert_eq!(
+            message.updated.unwrap().to_rfc3339(),
+            "2024-01-02T03:04:05+00:00"
+        );
+        let person: Person =
+            serde_json::from_str(r#"{"created":1704164645000,"lastActivity":""}"#).unwrap();
+        assert_eq!(
+            person.created.unwrap().to_rfc3339(),
+            "2024-01-02T03:04:05+00:00"
+        );
+        assert_eq!(person.last_activity, None);
+        let room: Room = serde_json::from_str(
+            r#"{"id":"r","type":"group","isLocked":false,"creatorId":"p","created":"yesterday"}"#,
+        )
+        .unwrap();
+        assert_eq!(room.created, None);
+        let data: crate::WebhookData =
+            serde_json::from_str(r#"{"id":"m","created":"2024-01-02T03:04:05.678Z"}"#).unwrap();
+        assert_eq!(
+            data.created.unwrap().to_rfc3339(),
+            "2024-01-02T03:04:05.678+00:00"
+        );
+    }
+
+    #[test]
+    fn datetime_params() {
+        let time = DateTime::parse_from_rfc3339("2024-01-02T03:04:05.678Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let params = MessageListParams {
+            before: Some(time),
+            ..MessageListParams::new("room")
+        };
+        assert_eq!(
+            serde_html_form::to_string(params).unwrap(),
+            "roomId=room&before=2024-01-02T03%3A04%3A05.678Z"
+        );
+    }
+
+    #[test]
+    fn person_list_params() {
+        let params = PersonListParams {
+            id: &["a", "b"],
+            calling_data: Some(true),
+            ..PersonListParams::default()
+        };
+        assert_eq!(
+            serde_html_form::to_string(params).unwrap(),
+            "id=a%2Cb&callingData=true"
+        );
+    }
+
+    #[test]
+    fn person_enums() {
+        let person: Person =
+            serde_json::from_str(r#"{"status":"OutOfOffice","type":"bot"}"#).unwrap();
+        assert_eq!(person.status, PresenceStatus::OutOfOffice);
+        assert_eq!(person.person_type, PersonType::Bot);
+        let person: Person =
+            serde_json::from_str(r#"{"status":"unknown","type":"appuser"}"#).unwrap();
+        assert_eq!(person.status, PresenceStatus::Unknown("unknown".into()));
+        assert_eq!(person.person_type, PersonType::AppUser);
+        let person: Person = serde_json::from_str(r#"{"status":"onVacation"}"#).unwrap();
+        assert_eq!(person.status, PresenceStatus::Unknown("onVacation".into()));
+        assert_eq!(person.person_type, PersonType::default());
+        assert_eq!(
+            serde_json::to_string(&PresenceStatus::DoNotDisturb).unwrap(),
+            r#""DoNotDisturb""#
+        );
+        let room: Room =
+            serde_json::from_str(r#"{"id":"r","type":"channel","isLocked":false,"creatorId":"c"}"#)
+                .unwrap();
+        assert_eq!(room.room_type, RoomType::Unknown("channel".into()));
+        assert_eq!(
+            serde_json::to_string(&RoomType::Group).unwrap(),
+            r#""group""#
+        );
+    }
+
     #[test]
     fn msg_is_created() {
         assert!(MessageActivity::Posted.is_created());
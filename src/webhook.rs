@@ -0,0 +1,367 @@
+#![deny(missing_docs)]
+//! Managing webhooks, and receiving the events they deliver
+
+use crate::{
+    error::Error, types::deserialize_timestamp, Actor, AuthorizationType, Event, EventData,
+    GlobalId, GlobalIdType, Webex, WebexEvent, Webhook, WebhookCreateParams, WebhookUpdateParams,
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use sha1::Sha1;
+use std::collections::HashMap;
+
+/// Name of the HTTP header holding the signature of a webhook delivery.
+pub const SIGNATURE_HEADER: &str = "X-Spark-Signature";
+
+/// Body of a webhook delivery.
+#[skip_serializing_none]
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WebhookPayload {
+    /// ID of the webhook that fired.
+    pub id: String,
+    /// Name of the webhook that fired.
+    pub name: String,
+    /// Resource type the event is about, e.g. `messages`.
+    pub resource: String,
+    /// What happened to the resource, e.g. `created`.
+    pub event: String,
+    /// Filter of the webhook that fired.
+    pub filter: Option<String>,
+    /// Organization of the webhook.
+    pub org_id: Option<String>,
+    /// Person who created the webhook.
+    pub created_by: Option<String>,
+    /// Whether the webhook is owned by the org or the creator.
+    pub owned_by: Option<String>,
+    /// Person who caused the event.
+    pub actor_id: Option<String>,
+    /// The resource itself. For messages and attachment actions, only the IDs are included.
+    pub data: WebhookData,
+}
+
+/// The resource a [`WebhookPayload`] is about.
+#[skip_serializing_none]
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WebhookData {
+    /// ID of the resource (message, membership, room, attachment action, ...).
+    pub id: String,
+    /// Room the resource belongs to.
+    pub room_id: Option<String>,
+    /// Person the resource belongs to, e.g. the author of a message or the member of a room.
+    pub person_id: Option<String>,
+    /// Email address of that person.
+    pub person_email: Option<String>,
+    /// When the resource was created.
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub created: Option<DateTime<Utc>>,
+    /// Any other fields of the resource.
+    #[serde(flatten)]
+    pub other: HashMap<String, serde_json::Value>,
+}
+
+impl From<&WebhookPayload> for WebexEvent {
+    /// Convert the delivery into the event the event stream would have produced. Deliveries that
+    /// have no equivalent are returned as [`WebexEvent::Unknown`] with an event type of
+    /// `"{resource}.{event}"`.
+    fn from(payload: &WebhookPayload) -> Self {
+        let data = &payload.data;
+        let id = |type_| GlobalId::new_with_cluster_unchecked(type_, data.id.clone(), None);
+        let room_id = data
+            .room_id
+            .clone()
+            .map(|room_id| GlobalId::new_with_cluster_unchecked(GlobalIdType::Room, room_id, None));
+        let person_id = payload
+            .actor_id
+            .clone()
+            .map(|actor| GlobalId::new_with_cluster_unchecked(GlobalIdType::Person, actor, None));
+        let member_id = || {
+            data.person_id.clone().map(|person_id| {
+                GlobalId::new_with_cluster_unchecked(GlobalIdType::Person, person_id, None)
+            })
+        };
+        match (payload.resource.as_str(), payload.event.as_str(), room_id) {
+            ("messages", "created", room_id) => Self::MessageCreated {
+                id: id(GlobalIdType::Message),
+                room_id,
+                person_id,
+                message: None,
+            },
+            ("messages", "deleted", _) => Self::MessageDeleted {
+                id: id(GlobalIdType::Message),
+                person_id,
+            },
+            ("attachmentActions", "created", _) => Self::CardSubmitted {
+                id: id(GlobalIdType::AttachmentAction),
+                person_id,
+                action: None,
+            },
+            ("memberships", "created", Some(room_id)) => Self::MembershipJoined {
+                room_id,
+                person_id,
+                member_id: member_id(),
+            },
+            ("memberships", "deleted", Some(room_id)) => Self::MembershipLeft {
+                room_id,
+                person_id,
+                member_id: member_id(),
+            },
+            ("rooms", "created", _) => Self::RoomCreated {
+                room_id: id(GlobalIdType::Room),
+                person_id,
+            },
+            ("rooms", "updated", _) => Self::RoomUpdated {
+                room_id: id(GlobalIdType::Room),
+                person_id,
+            },
+            (resource, event, _) => Self::Unknown(Box::new(Event {
+                id: data.id.clone(),
+                data: EventData {
+                    event_type: format!("{resource}.{event}"),
+                    actor: payload.actor_id.clone().map(|id| Actor {
+                        id,
+                        ..Actor::default()
+                    }),
+                    ..EventData::default()
+                },
+                ..Event::default()
+            })),
+        }
+    }
+}
+
+/// Checks and parses webhook deliveries, independently of the HTTP server receiving them.
+///
+/// ```
+/// # fn handle(body: &[u8], signature: Option<&str>) -> Result<(), webex::error::Error> {
+/// let receiver = webex::WebhookReceiver::new("webhook secret");
+/// // `signature` is the value of the `X-Spark-Signature` header, if any
+/// let payload = receiver.receive(body, signature)?;
+/// let event = webex::WebexEvent::from(&payload);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct WebhookReceiver {
+    secret: Option<Vec<u8>>,
+}
+
+impl WebhookReceiver {
+    /// Receiver for webhooks created with `secret`. Deliveries without a valid signature are
+    /// rejected.
+    #[must_use]
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            secret: Some(secret.as_ref().to_vec()),
+        }
+    }
+
+    /// Receiver for webhooks created without a secret. Anyone who knows the target URL can forge
+    /// deliveries, so only use this if the URL is protected some other way.
+    #[must_use]
+    pub const fn unverified() -> Self {
+        Self { secret: None }
+    }
+
+    /// Check the signature (the [`SIGNATURE_HEADER`] value, a hex HMAC-SHA1 of the raw body).
+    ///
+    /// # Errors
+    /// * [`Error::Authentication`] - if the signature is missing or doesn't match.
+    pub fn verify(&self, body: &[u8], signature: Option<&str>) -> Result<(), Error> {
+        let Some(secret) = &self.secret else {
+            return Ok(());
+        };
+        let signature = signature
+            .and_then(|signature| decode_hex(signature.trim()))
+            .ok_or(Error::Authentication(None))?;
+        let mut mac = Hmac::<Sha1>::new_from_slice(secret).map_err(|e| e.to_string())?;
+        mac.update(body);
+        mac.verify_slice(&signature)
+            .map_err(|_| Error::Authentication(None))
+    }
+
+    /// Verify the signature of a delivery, then parse its body.
+    ///
+    /// # Errors
+    /// * [`Error::Authentication`] - if the signature is missing or doesn't match.
+    /// * [`Error::Json`] - if the body is not a webhook payload.
+    pub fn receive(&self, body: &[u8], signature: Option<&str>) -> Result<WebhookPayload, Error> {
+        self.verify(body, signature)?;
+        Ok(serde_json::from_slice(body)?)
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+impl Webex {
+    /// Create a webhook. Deliveries can be checked and parsed with a [`WebhookReceiver`]; list
+    /// and delete webhooks with [`list::<Webhook>()`](Self::list) and
+    /// [`delete::<Webhook>()`](Self::delete).
+    ///
+    /// # Errors
+    /// * [`Error::Throttled`] - returned on HTTP 423/429 with an optional Retry-After, once the
+    ///   [`RetryPolicy`](crate::retry::RetryPolicy) gives up.
+    /// * [`Error::Authentication`] - if the token is invalid or expired.
+    /// * [`Error::Api`] - returned on any other non-2xx response, e.g. if the
+    ///   filter is invalid for the resource.
+    /// * [`Error::Json`] - returned when the return value cannot be deserialised. (If this
+    ///   happens, this is a library bug and should be reported.)
+    pub async fn create_webhook(&self, params: &WebhookCreateParams<'_>) -> Result<Webhook, Error> {
+        self.client
+            .api_post(
+                "webhooks",
+                params,
+                None::<()>,
+                AuthorizationType::Bearer(&self.token),
+            )
+            .await
+    }
+
+    /// Update a webhook, e.g. to change its target URL or secret, or to re-enable it.
+    ///
+    /// # Errors
+    /// * [`Error::Throttled`] - returned on HTTP 423/429 with an optional Retry-After, once the
+    ///   [`RetryPolicy`](crate::retry::RetryPolicy) gives up.
+    /// * [`Error::NotFound`] - if the webhook doesn't exist.
+    /// * [`Error::Authentication`] - if the token is invalid or expired.
+    /// * [`Error::Api`] - returned on any other non-2xx response.
+    /// * [`Error::Json`] - returned when the return value cannot be deserialised. (If this
+    ///   happens, this is a library bug and should be reported.)
+    pub async fn update_webhook(
+        &self,
+        webhook_id: &GlobalId,
+        params: &WebhookUpdateParams<'_>,
+    ) -> Result<Webhook, Error> {
+        let rest_method = format!("webhooks/{}", webhook_id.id());
+        self.client
+            .api_put(
+                &rest_method,
+                params,
+                None::<()>,
+                AuthorizationType::Bearer(&self.token),
+            )
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::mock_server;
+
+    const PAYLOAD: &str = r#"{"id":"w","name":"bot","resource":"messages","event":"created","actorId":"p","data":{"id":"m","roomId":"r","personId":"p","roomType":"group"}}"#;
+    // HMAC-SHA1 of PAYLOAD with the key "secret"
+    const SIGNATURE: &str = "eae532916fccf0ebcacf5e98ed9c60455445e46a";
+
+    #[test]
+    fn verifies_signatures() {
+        let receiver = WebhookReceiver::new("secret");
+        let payload = receiver
+            .receive(PAYLOAD.as_bytes(), Some(SIGNATURE))
+            .unwrap();
+        assert_eq!(payload.data.other["roomType"], "group");
+        let tampered = PAYLOAD.replace("\"m\"", "\"n\"");
+        assert!(matches!(
+            receiver.receive(tampered.as_bytes(), Some(SIGNATURE)),
+            Err(Error::Authentication(None))
+        ));
+        assert!(matches!(
+            receiver.receive(PAYLOAD.as_bytes(), None),
+            Err(Error::Authentication(None))
+        ));
+        assert!(WebhookReceiver::unverified()
+            .receive(PAYLOAD.as_bytes(), None)
+            .is_ok());
+    }
+
+    #[test]
+    fn converts_to_events() {
+        let payload: WebhookPayload = serde_json::from_str(PAYLOAD).unwrap();
+        let WebexEvent::MessageCreated {
+            id,
+            room_id,
+            person_id,
+            ..
+        } = WebexEvent::from(&payload)
+        else {
+            panic!("expected MessageCreated");
+        };
+        assert_eq!(id.id(), "m");
+        assert_eq!(room_id.unwrap().id(), "r");
+        assert_eq!(person_id.unwrap().id(), "p");
+
+        let payload = WebhookPayload {
+            resource: "meetings".to_string(),
+            event: "started".to_string(),
+            ..WebhookPayload::default()
+        };
+        let WebexEvent::Unknown(event) = WebexEvent::from(&payload) else {
+            panic!("expected Unknown");
+        };
+        assert_eq!(event.data.event_type, "meetings.started");
+    }
+
+    #[tokio::test]
+    async fn webhook_crud() {
+        let webhook = r#"{"id":"w","name":"bot","targetUrl":"https://example.com/hook","resource":"messages","event":"created","status":"active"}"#;
+        let (prefix, requests) = mock_server(vec![
+            ("200 OK", "", webhook.to_string()),
+            ("200 OK", "", format!(r#"{{"items":[{webhook}]}}"#)),
+            ("200 OK", "", webhook.to_string()),
+            ("204 No Content", "", String::new()),
+        ])
+        .await;
+        let webex = Webex::builder("token")
+            .rest_url(prefix)
+            .wdm_url("http://localhost")
+            .build()
+            .await
+            .unwrap();
+        let created = webex
+            .create_webhook(&WebhookCreateParams {
+                name: "bot",
+                target_url: "https://example.com/hook",
+                resource: "messages",
+                event: "created",
+                secret: Some("secret"),
+                ..WebhookCreateParams::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(webex.list::<Webhook>().await.unwrap()[0], created);
+        let id = GlobalId::new_with_cluster_unchecked(GlobalIdType::Webhook, created.id, None);
+        webex
+            .update_webhook(
+                &id,
+                &WebhookUpdateParams {
+                    name: "bot",
+                    target_url: "https://example.com/hook",
+                    status: Some("active"),
+                    ..WebhookUpdateParams::default()
+                },
+            )
+            .await
+            .unwrap();
+        webex.delete::<Webhook>(&id).await.unwrap();
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                "POST /webhooks HTTP/1.1",
+                "GET /webhooks HTTP/1.1",
+                "PUT /webhooks/w HTTP/1.1",
+                "DELETE /webhooks/w HTTP/1.1",
+            ]
+        );
+    }
+}